@@ -0,0 +1,275 @@
+//! The markup produced for `:::name` admonition blocks.
+
+use crate::parser::Attributes;
+use crate::style::{add_attributes, escape_html, StyleBackend};
+use std::collections::HashMap;
+
+/// The placeholder replaced by the directive name in a template.
+const NAME_PLACEHOLDER: &str = "{name}";
+/// The placeholder replaced by the title in a title template.
+const TITLE_PLACEHOLDER: &str = "{title}";
+
+/// The opening and closing markup of an admonition.
+///
+/// Both templates may contain `{name}`, which is replaced by the name used
+/// in the document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Admonition {
+    pub open: String,
+    pub close: String,
+    /// The markup for a title, with `{title}` replaced by the title. `None`
+    /// uses the registry's title markup.
+    pub title: Option<String>,
+}
+
+impl Admonition {
+    /// Creates an admonition from its opening and closing templates.
+    pub fn new(open: impl Into<String>, close: impl Into<String>) -> Self {
+        Self {
+            open: open.into(),
+            close: close.into(),
+            title: None,
+        }
+    }
+
+    /// Sets the markup used for this admonition's title.
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    /// Fills in the templates for a directive.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name the directive was written with.
+    ///
+    /// # Returns
+    ///
+    /// The opening and closing markup.
+    pub fn render(&self, name: &str) -> (String, String) {
+        (
+            self.open.replace(NAME_PLACEHOLDER, name),
+            self.close.replace(NAME_PLACEHOLDER, name),
+        )
+    }
+}
+
+/// Maps directive names to the markup they produce.
+///
+/// The default registry contains the built-in admonitions (`alert`, `info`,
+/// `success`, `warning`, `error`, `tip`, `fold`, `summary`, `col` and
+/// `card`) of the default [`StyleBackend`], see
+/// [`StyleBackend::admonitions`] for the other backends. Registering a name
+/// again replaces its entry, and names without an entry use the fallback,
+/// `<div class="{name}">`.
+///
+/// A title on the opening line (`:::warning Back up first`) is rendered with
+/// the title markup right after the opening tag, and an attribute block
+/// (`{#id .class key=value}`) is added to the opening tag.
+///
+/// ```
+/// use draftsmith_render::admonition::AdmonitionRegistry;
+///
+/// let registry = AdmonitionRegistry::default()
+///     .with("danger", "<div class=\"callout callout-danger\">", "</div>")
+///     .with("tip", "<aside class=\"callout tip\">", "</aside>");
+/// assert_eq!(
+///     registry.render("danger", "", &Default::default()).0,
+///     "<div class=\"callout callout-danger\">"
+/// );
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdmonitionRegistry {
+    entries: HashMap<String, Admonition>,
+    fallback: Admonition,
+    title: String,
+}
+
+impl AdmonitionRegistry {
+    /// Creates a registry without any entries.
+    pub fn empty() -> Self {
+        Self {
+            entries: HashMap::new(),
+            fallback: Admonition::new("<div class=\"{name}\">", "</div>"),
+            title: "<p class=\"admonition-title\">{title}</p>".to_string(),
+        }
+    }
+
+    /// Adds or replaces the entry for `name`.
+    ///
+    /// # Returns
+    ///
+    /// The entry previously registered under `name`, if any.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        open: impl Into<String>,
+        close: impl Into<String>,
+    ) -> Option<Admonition> {
+        self.insert(name, Admonition::new(open, close))
+    }
+
+    /// Adds or replaces the entry for `name` with a complete [`Admonition`].
+    pub fn insert(
+        &mut self,
+        name: impl Into<String>,
+        admonition: Admonition,
+    ) -> Option<Admonition> {
+        self.entries.insert(name.into(), admonition)
+    }
+
+    /// Adds or replaces the entry for `name`, returning the registry.
+    pub fn with(
+        mut self,
+        name: impl Into<String>,
+        open: impl Into<String>,
+        close: impl Into<String>,
+    ) -> Self {
+        self.register(name, open, close);
+        self
+    }
+
+    /// Adds or replaces the entry for `name` with a complete [`Admonition`],
+    /// returning the registry.
+    pub fn with_admonition(mut self, name: impl Into<String>, admonition: Admonition) -> Self {
+        self.insert(name, admonition);
+        self
+    }
+
+    /// Removes the entry for `name`, so that it uses the fallback.
+    pub fn remove(&mut self, name: &str) -> Option<Admonition> {
+        self.entries.remove(name)
+    }
+
+    /// Sets the markup used for names without an entry.
+    pub fn set_fallback(&mut self, open: impl Into<String>, close: impl Into<String>) {
+        self.fallback = Admonition::new(open, close);
+    }
+
+    /// Sets the markup used for titles, with `{title}` replaced by the title.
+    ///
+    /// Entries with their own title markup keep it.
+    pub fn set_title(&mut self, title: impl Into<String>) {
+        self.title = title.into();
+    }
+
+    /// Sets the markup used for titles, returning the registry.
+    pub fn with_title(mut self, title: impl Into<String>) -> Self {
+        self.set_title(title);
+        self
+    }
+
+    /// The entry registered for `name`.
+    pub fn get(&self, name: &str) -> Option<&Admonition> {
+        self.entries.get(name)
+    }
+
+    /// The names with an entry, in no particular order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+
+    /// The opening and closing markup for a directive, using the fallback
+    /// for unknown names.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name the directive was written with.
+    /// * `title` - The title from the opening line, empty for none.
+    /// * `attributes` - The attribute block from the opening line.
+    pub fn render(&self, name: &str, title: &str, attributes: &Attributes) -> (String, String) {
+        let admonition = self.get(name).unwrap_or(&self.fallback);
+        // The fallback puts any name into the markup
+        let (open, close) = admonition.render(&escape_html(name));
+        let mut open = add_attributes(&open, attributes);
+        if !title.is_empty() {
+            let template = admonition.title.as_ref().unwrap_or(&self.title);
+            open.push_str(&template.replace(TITLE_PLACEHOLDER, &escape_html(title)));
+        }
+        (open, close)
+    }
+}
+
+impl Default for AdmonitionRegistry {
+    fn default() -> Self {
+        StyleBackend::default().admonitions()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_and_fallback() {
+        let registry = AdmonitionRegistry::default();
+        let none = Attributes::default();
+
+        assert_eq!(
+            registry.render("tip", "", &none),
+            (
+                "<div class=\"admonition tip\">".to_string(),
+                "</div>".to_string()
+            )
+        );
+        assert_eq!(
+            registry.render("fold", "", &none).1,
+            "</details>".to_string(),
+            "fold should close its details element"
+        );
+        assert_eq!(
+            registry.render("quote", "", &none),
+            ("<div class=\"quote\">".to_string(), "</div>".to_string())
+        );
+        assert_eq!(
+            registry.render("a\"><b", "", &none).0,
+            "<div class=\"a&quot;&gt;&lt;b\">"
+        );
+    }
+
+    #[test]
+    fn test_override_and_fallback() {
+        let mut registry = AdmonitionRegistry::default()
+            .with("tip", "<aside class=\"hint\">", "</aside>")
+            .with("example", "<div class=\"box {name}\">", "</div>");
+        registry.set_fallback("<section class=\"{name}\">", "</section>");
+        let none = Attributes::default();
+
+        assert!(registry.remove("card").is_some());
+        assert_eq!(
+            registry.render("tip", "", &none).0,
+            "<aside class=\"hint\">"
+        );
+        assert_eq!(
+            registry.render("example", "", &none).0,
+            "<div class=\"box example\">"
+        );
+        assert_eq!(
+            registry.render("card", "", &none),
+            (
+                "<section class=\"card\">".to_string(),
+                "</section>".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn test_title_and_attributes() {
+        let mut registry = AdmonitionRegistry::default();
+        registry.insert(
+            "quote",
+            Admonition::new("<blockquote>", "</blockquote>").with_title("<cite>{title}</cite>"),
+        );
+        let attributes = Attributes::parse("#backup .wide data-x=1").unwrap();
+
+        assert_eq!(
+            registry.render("warning", "Back up <first>", &attributes).0,
+            "<div class=\"admonition important wide\" id=\"backup\" data-x=\"1\"><p class=\"admonition-title\">Back up &lt;first&gt;</p>"
+        );
+        assert_eq!(
+            registry.render("quote", "Ada", &Attributes::default()).0,
+            "<blockquote><cite>Ada</cite>"
+        );
+    }
+}
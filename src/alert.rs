@@ -0,0 +1,254 @@
+//! GitHub-style `> [!NOTE]` alerts and Obsidian callouts, rendered as
+//! admonitions.
+
+use crate::admonition::AdmonitionRegistry;
+use crate::parser::Attributes;
+use comrak::nodes::{Ast, AstNode, NodeHtmlBlock, NodeValue};
+use comrak::Arena;
+use std::cell::RefCell;
+
+/// The GitHub alert types and the admonitions they are rendered as.
+pub const GITHUB_ALERTS: [(&str, &str); 5] = [
+    ("note", "info"),
+    ("tip", "tip"),
+    ("important", "alert"),
+    ("warning", "warning"),
+    ("caution", "error"),
+];
+
+/// The Obsidian callout types, with their aliases, and the admonitions they
+/// are rendered as. Other types use the fallback markup of `callout`.
+pub const OBSIDIAN_CALLOUTS: [(&str, &str); 28] = [
+    ("note", "info"),
+    ("abstract", "info"),
+    ("summary", "info"),
+    ("tldr", "info"),
+    ("info", "info"),
+    ("todo", "info"),
+    ("tip", "tip"),
+    ("hint", "tip"),
+    ("important", "tip"),
+    ("success", "success"),
+    ("check", "success"),
+    ("done", "success"),
+    ("question", "alert"),
+    ("help", "alert"),
+    ("faq", "alert"),
+    ("warning", "warning"),
+    ("caution", "warning"),
+    ("attention", "warning"),
+    ("failure", "error"),
+    ("fail", "error"),
+    ("missing", "error"),
+    ("danger", "error"),
+    ("error", "error"),
+    ("bug", "error"),
+    ("example", "info"),
+    ("quote", "info"),
+    ("cite", "info"),
+    ("callout", "callout"),
+];
+
+/// Replaces every block quote starting with a `[!TYPE]` line by the
+/// admonition registered for that type.
+///
+/// The alert's title is its type, e.g. "Note". Block quotes with an unknown
+/// type are left alone, as GitHub does.
+///
+/// # Arguments
+///
+/// * `arena` - The arena the document was parsed into.
+/// * `root` - The document's root node.
+/// * `admonitions` - The markup for each admonition.
+pub(crate) fn render_alerts<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    admonitions: &AdmonitionRegistry,
+) {
+    replace_quotes(arena, root, |line| {
+        let kind = line.strip_prefix("[!")?.strip_suffix(']')?.to_lowercase();
+        let (_, name) = GITHUB_ALERTS.iter().find(|(alert, _)| *alert == kind)?;
+        Some(admonitions.render(name, &title_case(&kind), &Attributes::default()))
+    });
+}
+
+/// Replaces every block quote starting with an Obsidian callout line,
+/// `[!type]`, by an admonition.
+///
+/// Text after the type is the title, which defaults to the type. `[!type]-`
+/// and `[!type]+` wrap the contents in a closed or open `:::fold`. Every
+/// callout gets a `callout-{type}` class, and types missing from
+/// [`OBSIDIAN_CALLOUTS`] are rendered as a `callout`.
+///
+/// # Arguments
+///
+/// * `arena` - The arena the document was parsed into.
+/// * `root` - The document's root node.
+/// * `admonitions` - The markup for each admonition.
+pub(crate) fn render_callouts<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    admonitions: &AdmonitionRegistry,
+) {
+    replace_quotes(arena, root, |line| {
+        let (kind, rest) = line.strip_prefix("[!")?.split_once(']')?;
+        if kind.is_empty() || !kind.chars().all(|c| c.is_alphanumeric() || c == '-') {
+            return None;
+        }
+        let kind = kind.to_lowercase();
+        let (fold, title) = match rest.strip_prefix('-') {
+            Some(title) => (Some(false), title),
+            None => match rest.strip_prefix('+') {
+                Some(title) => (Some(true), title),
+                None => (None, rest),
+            },
+        };
+        let title = match title.trim() {
+            "" => title_case(&kind),
+            title => title.to_string(),
+        };
+        let name = OBSIDIAN_CALLOUTS
+            .iter()
+            .find(|(callout, _)| *callout == kind)
+            .map_or("callout", |(_, name)| name);
+        let attributes = Attributes {
+            classes: vec![format!("callout-{}", kind)],
+            ..Attributes::default()
+        };
+
+        Some(match fold {
+            None => admonitions.render(name, &title, &attributes),
+            Some(open) => {
+                let (outer_open, outer_close) = admonitions.render(name, "", &attributes);
+                let details = Attributes {
+                    pairs: if open {
+                        vec![("open".to_string(), String::new())]
+                    } else {
+                        Vec::new()
+                    },
+                    ..Attributes::default()
+                };
+                let (fold_open, fold_close) = admonitions.render("fold", &title, &details);
+                (
+                    outer_open + fold_open.as_str(),
+                    fold_close + outer_close.as_str(),
+                )
+            }
+        })
+    });
+}
+
+/// Replaces block quotes by the markup `markup` returns for their first
+/// line, leaving quotes for which it returns `None` alone.
+fn replace_quotes<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    root: &'a AstNode<'a>,
+    markup: impl Fn(&str) -> Option<(String, String)>,
+) {
+    // Collect first, the tree cannot change while it is being walked
+    let quotes: Vec<_> = root
+        .descendants()
+        .filter(|node| matches!(node.data.borrow().value, NodeValue::BlockQuote))
+        .collect();
+
+    for quote in quotes {
+        let Some(paragraph) = quote.first_child() else {
+            continue;
+        };
+        let Some((line, nodes)) = first_line(paragraph) else {
+            continue;
+        };
+        let Some((open, close)) = markup(&line) else {
+            continue;
+        };
+        remove_line(paragraph, nodes);
+        replace_with_children(arena, quote, open, close);
+    }
+}
+
+/// Reads the first line of a paragraph.
+///
+/// # Returns
+///
+/// The trimmed plain text of the line and the nodes holding it, or `None`
+/// if the node is not a paragraph.
+fn first_line<'a>(paragraph: &'a AstNode<'a>) -> Option<(String, Vec<&'a AstNode<'a>>)> {
+    if !matches!(paragraph.data.borrow().value, NodeValue::Paragraph) {
+        return None;
+    }
+    let mut line = String::new();
+    let mut nodes = Vec::new();
+    for child in paragraph.children() {
+        if matches!(
+            child.data.borrow().value,
+            NodeValue::SoftBreak | NodeValue::LineBreak
+        ) {
+            break;
+        }
+        // Formatting in a title is kept as plain text
+        for node in child.descendants() {
+            match &node.data.borrow().value {
+                NodeValue::Text(text) => line.push_str(text),
+                NodeValue::Code(code) => line.push_str(&code.literal),
+                _ => {}
+            }
+        }
+        nodes.push(child);
+    }
+    Some((line.trim().to_string(), nodes))
+}
+
+/// Removes the nodes of a paragraph's first line and the line break after
+/// them, and the paragraph itself if nothing is left.
+fn remove_line<'a>(paragraph: &'a AstNode<'a>, nodes: Vec<&'a AstNode<'a>>) {
+    let next = nodes.last().and_then(|node| node.next_sibling());
+    for node in nodes {
+        node.detach();
+    }
+    if let Some(next) = next {
+        if matches!(
+            next.data.borrow().value,
+            NodeValue::SoftBreak | NodeValue::LineBreak
+        ) {
+            next.detach();
+        }
+    }
+    if paragraph.first_child().is_none() {
+        paragraph.detach();
+    }
+}
+
+/// Replaces a node by its children, wrapped in raw HTML.
+fn replace_with_children<'a>(
+    arena: &'a Arena<AstNode<'a>>,
+    node: &'a AstNode<'a>,
+    open: String,
+    close: String,
+) {
+    let start = node.data.borrow().sourcepos.start;
+    let html_block = |literal: String| {
+        arena.alloc(AstNode::new(RefCell::new(Ast::new(
+            NodeValue::HtmlBlock(NodeHtmlBlock {
+                block_type: 6,
+                literal: literal + "\n",
+            }),
+            start,
+        ))))
+    };
+
+    node.insert_before(html_block(open));
+    while let Some(child) = node.first_child() {
+        node.insert_before(child);
+    }
+    node.insert_before(html_block(close));
+    node.detach();
+}
+
+/// Capitalises the first letter of a word, e.g. `note` to `Note`.
+fn title_case(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
@@ -1,4 +1,5 @@
-use draftsmith_render::parse_md_to_html;
+use draftsmith_render::options::RenderOptions;
+use draftsmith_render::try_parse_md_to_html;
 use std::error::Error;
 use std::fs;
 
@@ -17,9 +18,12 @@ fn main() -> Result<(), Box<dyn Error>> {
     let test_string = fs::read_to_string(input_path)?;
 
     // Create the HTML from the markdown string
-    let html = parse_md_to_html(&test_string, Option::None);
+    let rendered = try_parse_md_to_html(&test_string, Option::None, &RenderOptions::default())?;
 
-    println!("{}", html);
+    for diagnostic in &rendered.diagnostics {
+        eprintln!("{input_path}:{diagnostic}");
+    }
+    println!("{}", rendered.output);
 
     Ok(())
 }
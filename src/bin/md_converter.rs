@@ -1,8 +1,15 @@
 use clap::Parser;
+use draftsmith_render::error::RenderError;
+use draftsmith_render::options::RenderOptions;
+use draftsmith_render::parser::Syntax;
 use draftsmith_render::processor::Processor;
+use draftsmith_render::renderer::Renderer;
+use draftsmith_render::script::{Prelude, ScriptPackages};
+use draftsmith_render::style::StyleBackend;
 use std::fs;
 use std::io::{self, Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
@@ -18,11 +25,76 @@ struct Cli {
     /// Output format (html or markdown)
     #[clap(short, long, default_value = "html")]
     format: String,
+
+    /// Markup style (daisyui, bootstrap or semantic)
+    #[clap(short, long, default_value = "daisyui")]
+    style: StyleBackend,
+
+    /// Directive syntax of the input (draftsmith, pandoc or myst)
+    #[clap(long, default_value = "draftsmith")]
+    syntax: Syntax,
+
+    /// Rhai packages available to scripts (minimal, basic, standard, or a
+    /// comma-separated list such as core,math,strings)
+    #[clap(long, default_value = "basic")]
+    packages: ScriptPackages,
+
+    /// Directory that Rhai `import` statements are resolved in
+    #[clap(long)]
+    modules: Option<PathBuf>,
+
+    /// Rhai script whose functions and constants every chunk and expression
+    /// can use (may be repeated)
+    #[clap(long = "prelude", value_name = "FILE")]
+    preludes: Vec<PathBuf>,
+
+    /// TOML configuration file, e.g. with `prelude = ["lib.rhai"]`
+    #[clap(short, long)]
+    config: Option<PathBuf>,
 }
 
-fn main() -> io::Result<()> {
-    let cli = Cli::parse();
+/// Reads the prelude scripts listed in a configuration file.
+///
+/// # Returns
+///
+/// The scripts' paths, relative to the configuration file's directory.
+fn config_preludes(path: &Path) -> Result<Vec<PathBuf>, RenderError> {
+    let invalid = |message: String| RenderError::Config {
+        path: path.to_path_buf(),
+        message,
+    };
+    let config: toml::Table = fs::read_to_string(path)?
+        .parse()
+        .map_err(|err: toml::de::Error| invalid(err.message().to_string()))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+
+    match config.get("prelude") {
+        None => Ok(Vec::new()),
+        Some(toml::Value::String(file)) => Ok(vec![dir.join(file)]),
+        Some(toml::Value::Array(files)) => files
+            .iter()
+            .map(|file| match file.as_str() {
+                Some(file) => Ok(dir.join(file)),
+                None => Err(invalid("`prelude` must list file names".to_string())),
+            })
+            .collect(),
+        Some(_) => Err(invalid(
+            "`prelude` must be a file name or a list of them".to_string(),
+        )),
+    }
+}
+
+fn main() -> ExitCode {
+    match run(Cli::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
 
+fn run(cli: Cli) -> Result<(), RenderError> {
     // Read input from file or stdin
     let input = if let Some(input_path) = &cli.input {
         fs::read_to_string(input_path)?
@@ -32,14 +104,44 @@ fn main() -> io::Result<()> {
         buffer
     };
 
-    let output_content = match cli.format.as_str() {
+    // Preludes from the configuration file come first
+    let mut prelude_paths = match &cli.config {
+        Some(config) => config_preludes(config)?,
+        None => Vec::new(),
+    };
+    prelude_paths.extend(cli.preludes.iter().cloned());
+    let preludes = prelude_paths
+        .iter()
+        .map(Prelude::from_file)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let rendered = match cli.format.as_str() {
         "html" => {
             // Convert Markdown to HTML
-            draftsmith_render::parse_md_to_html(&input, Option::None)
+            let mut renderer = Renderer::new(Option::None, RenderOptions::default())
+                .with_style(cli.style)
+                .with_syntax(cli.syntax)
+                .with_packages(cli.packages);
+            if let Some(modules) = &cli.modules {
+                renderer = renderer.with_modules(modules);
+            }
+            for prelude in preludes {
+                renderer = renderer.with_prelude(prelude);
+            }
+            renderer.render(&input)?
         }
         "markdown" | "md" => {
             // Assuming `Processor` can process and convert input to markdown if necessary.
-            let mut processor = Processor::default();
+            let mut processor = Processor::default()
+                .with_style(cli.style)
+                .with_syntax(cli.syntax)
+                .with_packages(cli.packages);
+            if let Some(modules) = &cli.modules {
+                processor = processor.with_modules(modules);
+            }
+            for prelude in preludes {
+                processor = processor.with_prelude(prelude);
+            }
             processor.process(&input)
         }
         _ => {
@@ -48,12 +150,54 @@ fn main() -> io::Result<()> {
         }
     };
 
+    // Report problems without mixing them into the output
+    let source = cli
+        .input
+        .as_ref()
+        .map_or_else(|| "<stdin>".to_string(), |path| path.display().to_string());
+    for diagnostic in &rendered.diagnostics {
+        eprintln!("{source}:{diagnostic}");
+    }
+
     // Write output to file or stdout
     if let Some(output_path) = &cli.output {
-        fs::write(output_path, &output_content)?;
+        fs::write(output_path, &rendered.output)?;
     } else {
-        io::stdout().write_all(output_content.as_bytes())?;
+        io::stdout().write_all(rendered.output.as_bytes())?;
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIGS: &str = "tests/fixtures/config";
+
+    #[test]
+    fn test_config_preludes() {
+        let dir = Path::new(CONFIGS);
+        assert_eq!(
+            config_preludes(&dir.join("single.toml")).unwrap(),
+            vec![dir.join("lib.rhai")]
+        );
+        // Paths are relative to the configuration file, not the working
+        // directory
+        assert_eq!(
+            config_preludes(&dir.join("list.toml")).unwrap(),
+            vec![
+                dir.join("lib.rhai"),
+                dir.join("../scripts/helpers/format.rhai")
+            ]
+        );
+
+        for name in ["invalid.toml", "invalid_list.toml"] {
+            let path = dir.join(name);
+            match config_preludes(&path) {
+                Err(RenderError::Config { path: reported, .. }) => assert_eq!(reported, path),
+                other => panic!("Expected a configuration error, found {:?}", other),
+            }
+        }
+    }
+}
@@ -10,7 +10,7 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     // Mutable as it stores the rhai environment scope
     let mut markdown_processor = Processor::default();
-    let document = markdown_processor.process(&test_string);
+    let document = markdown_processor.process(&test_string).output;
 
     println!("{document}");
 
@@ -1,68 +1,86 @@
+use draftsmith_render::extension::{DraftsmithExtension, Registrar};
 use draftsmith_render::processor::Processor;
-use rhai::Engine;
 use std::error::Error;
 use std::fs;
-pub type CustomFn = Box<dyn Fn(&mut Engine)>;
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let input_path = "tests/fixtures/custom_rhai_functions.md";
-    let _expected_path = "tests/fixtures/custom_rhai_functions_expected.md";
-
-    let test_string = fs::read_to_string(input_path)?;
+/// Example functions, with the separator used by the diamond kept as state.
+struct Examples {
+    separator: String,
+}
 
-    // Register custom functions
-    fn double(x: i64) -> i64 {
-        x * 2
+impl DraftsmithExtension for Examples {
+    fn name(&self) -> &str {
+        "examples"
     }
-    fn concat(a: String, b: String) -> String {
-        format!("{}{}", a, b)
+
+    fn description(&self) -> &str {
+        "Functions used by tests/fixtures/custom_rhai_functions.md"
     }
-    let separator = "¶"; // This will be cloned into the closure below
-    let sep2 = "$"; // The closure will take an immutable reference to this string
-    let functions: Vec<CustomFn> = vec![
-        Box::new(|engine: &mut Engine| {
-            engine.register_fn("double", double);
-        }),
-        Box::new(|engine: &mut Engine| {
-            engine.register_fn("concat", concat);
-        }),
-        Box::new(move |engine: &mut Engine| {
-            let separator = separator.to_string(); // Clone it here so we can move it into the next closure
-            engine.register_fn("generate_ascii_diamond", move |size: i64| -> String {
-                if size == 0 {
-                    println!("Size must be greater than 0.");
-                    return "".to_string();
-                }
 
-                let separator = format!("{separator}{sep2}");
+    fn register(&self, registrar: &mut Registrar) {
+        fn double(x: i64) -> i64 {
+            x * 2
+        }
+        fn concat(a: String, b: String) -> String {
+            format!("{}{}", a, b)
+        }
+
+        let separator = self.separator.clone(); // Moved into the closure below
+        registrar
+            .function("double", "Doubles a number.", double)
+            .function("concat", "Joins two strings.", concat)
+            .function(
+                "generate_ascii_diamond",
+                "Draws a diamond of the given size.",
+                move |size: i64| -> String {
+                    if size == 0 {
+                        println!("Size must be greater than 0.");
+                        return "".to_string();
+                    }
+
+                    let mut output = String::new();
 
-                let separator = format!("{separator}{sep2}");
+                    // Upper part of the diamond including the middle line
+                    for i in 0..size {
+                        let spaces = " ".repeat((size - i) as usize);
+                        let stars = separator.repeat((2 * i + 1) as usize);
+                        let line = format!("{spaces}{stars}\n");
+                        output.push_str(&line);
+                    }
 
-                let mut output = String::new();
+                    // Lower part of the diamond
+                    for i in (0..size - 1).rev() {
+                        let spaces = " ".repeat((size - i) as usize);
+                        let stars = separator.repeat((2 * i + 1) as usize);
+                        let line = format!("{spaces}{stars}\n");
+                        output.push_str(&line);
+                    }
+                    format!("<pre>\n{}\n</pre>", output)
+                },
+            );
+    }
+}
 
-                // Upper part of the diamond including the middle line
-                for i in 0..size {
-                    let spaces = " ".repeat((size - i) as usize);
-                    let stars = separator.repeat((2 * i + 1) as usize);
-                    let line = format!("{spaces}{stars}\n");
-                    output.push_str(&line);
-                }
+fn main() -> Result<(), Box<dyn Error>> {
+    let input_path = "tests/fixtures/custom_rhai_functions.md";
+    let _expected_path = "tests/fixtures/custom_rhai_functions_expected.md";
 
-                // Lower part of the diamond
-                for i in (0..size - 1).rev() {
-                    let spaces = " ".repeat((size - i) as usize);
-                    let stars = separator.repeat((2 * i + 1) as usize);
-                    let line = format!("{spaces}{stars}\n");
-                    output.push_str(&line);
-                }
-                format!("<pre>\n{}\n</pre>", output)
-            });
-        }),
-    ];
+    let test_string = fs::read_to_string(input_path)?;
+
+    let examples = Examples {
+        separator: "¶$$".to_string(),
+    };
+
+    // List what the extension provides
+    let info = examples.info();
+    eprintln!("{}: {}", info.name, info.description);
+    for function in &info.functions {
+        eprintln!("  {}/{}: {}", function.name, function.params, function.doc);
+    }
 
     // Processor is Mutable as it stores the rhai environment scope
-    let mut markdown_processor = Processor::new(Some(functions));
-    let document = markdown_processor.process(&test_string);
+    let mut markdown_processor = Processor::default().with_extension(examples);
+    let document = markdown_processor.process(&test_string).output;
 
     println!("{document}");
 
@@ -0,0 +1,148 @@
+//! Problems found while processing a document, with their source positions.
+
+use crate::parser::Span;
+use rhai::EvalAltResult;
+use std::fmt;
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The construct could not be rendered, e.g. a Rhai chunk failed.
+    Error,
+    /// The construct was rendered but is probably not what the author meant,
+    /// e.g. a stray `:::`.
+    Warning,
+}
+
+/// A problem at a position in the source document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    /// The 1-based line in the document.
+    pub line: usize,
+    /// The 1-based column in the document, counted in characters.
+    pub column: usize,
+    /// The source of the construct that caused the problem, e.g. `:::tip`,
+    /// ```` ```{rhai} ```` or `λ#(...)#`.
+    pub directive: String,
+    /// The byte range of the construct that caused the problem.
+    pub span: Span,
+}
+
+impl Diagnostic {
+    /// Creates an error at the start of `span`.
+    pub fn error(message: impl Into<String>, directive: impl Into<String>, span: Span) -> Self {
+        Self::new(Severity::Error, message.into(), directive.into(), span)
+    }
+
+    /// Creates a warning at the start of `span`.
+    pub fn warning(message: impl Into<String>, directive: impl Into<String>, span: Span) -> Self {
+        Self::new(Severity::Warning, message.into(), directive.into(), span)
+    }
+
+    fn new(severity: Severity, message: String, directive: String, span: Span) -> Self {
+        Self {
+            severity,
+            message,
+            line: span.line,
+            column: span.column,
+            directive,
+            span,
+        }
+    }
+
+    /// Creates an error from a failed Rhai evaluation.
+    ///
+    /// # Arguments
+    ///
+    /// * `err` - The error returned by the Rhai engine.
+    /// * `directive` - The construct that held the code.
+    /// * `span` - The construct's position in the document.
+    /// * `line` - The document line of the first line of code.
+    /// * `column` - The document column of the first character of code.
+    /// * `margin` - The document column the following lines of code start
+    ///   at, after the indentation or `>` of a list item or block quote.
+    ///
+    /// # Returns
+    ///
+    /// A `Diagnostic` positioned at the failing statement where Rhai reports
+    /// one, otherwise at the start of the code.
+    pub fn from_script_error(
+        mut err: EvalAltResult,
+        directive: impl Into<String>,
+        span: Span,
+        line: usize,
+        column: usize,
+        margin: usize,
+    ) -> Self {
+        let position = err.take_position();
+        let message = match &err {
+            // A script stopped by a limit carries the reason as its token
+            EvalAltResult::ErrorTerminated(reason, _) if reason.is_string() => reason.to_string(),
+            err => err.to_string(),
+        };
+        let mut diagnostic = Self::error(message, directive, span);
+        diagnostic.line = line;
+        diagnostic.column = column;
+        if let Some(script_line) = position.line() {
+            diagnostic.line = line + script_line - 1;
+            if script_line > 1 {
+                diagnostic.column = margin;
+            }
+            if let Some(script_column) = position.position() {
+                diagnostic.column += script_column - 1;
+            }
+        }
+        diagnostic
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}: {} ({})",
+            self.line, self.column, self.severity, self.message, self.directive
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rhai::Engine;
+
+    #[test]
+    fn test_script_error_position() {
+        let err = *Engine::new_raw()
+            .eval::<rhai::Dynamic>("let x = 1;\nlet y = x + z;")
+            .unwrap_err();
+        let span = Span {
+            start: 0,
+            end: 10,
+            line: 4,
+            column: 1,
+        };
+
+        let diagnostic = Diagnostic::from_script_error(err, "```{rhai}", span, 5, 1, 1);
+
+        assert_eq!(diagnostic.severity, Severity::Error);
+        assert_eq!(diagnostic.line, 6);
+        assert_eq!(diagnostic.column, 13);
+        assert_eq!(diagnostic.message, "Variable not found: z");
+        assert_eq!(
+            diagnostic.to_string(),
+            "6:13: error: Variable not found: z (```{rhai})"
+        );
+    }
+}
@@ -0,0 +1,74 @@
+//! Errors that stop a document from being rendered at all.
+//!
+//! Problems inside a document, such as a failing `{rhai}` chunk, are reported
+//! as [`Diagnostic`](crate::diagnostic::Diagnostic)s instead.
+
+use rhai::EvalAltResult;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::string::FromUtf8Error;
+
+/// An error raised while rendering a document.
+#[derive(Debug)]
+pub enum RenderError {
+    /// Comrak failed to write the HTML.
+    Format(io::Error),
+    /// The rendered HTML was not valid UTF-8.
+    Utf8(FromUtf8Error),
+    /// A script failed outside of a document, e.g. while setting up the engine.
+    Script(Box<EvalAltResult>),
+    /// Reading or writing a file failed.
+    Io(io::Error),
+    /// A configuration file could not be understood.
+    Config { path: PathBuf, message: String },
+}
+
+impl fmt::Display for RenderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderError::Format(err) => write!(f, "failed to format HTML: {}", err),
+            RenderError::Utf8(err) => write!(f, "rendered HTML is not valid UTF-8: {}", err),
+            RenderError::Script(err) => write!(f, "script error: {}", err),
+            RenderError::Io(err) => write!(f, "I/O error: {}", err),
+            RenderError::Config { path, message } => {
+                write!(
+                    f,
+                    "invalid configuration in {}: {}",
+                    path.display(),
+                    message
+                )
+            }
+        }
+    }
+}
+
+impl Error for RenderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RenderError::Format(err) | RenderError::Io(err) => Some(err),
+            RenderError::Utf8(err) => Some(err),
+            RenderError::Script(err) => Some(err.as_ref()),
+            RenderError::Config { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for RenderError {
+    fn from(err: io::Error) -> Self {
+        RenderError::Io(err)
+    }
+}
+
+impl From<FromUtf8Error> for RenderError {
+    fn from(err: FromUtf8Error) -> Self {
+        RenderError::Utf8(err)
+    }
+}
+
+impl From<Box<EvalAltResult>> for RenderError {
+    fn from(err: Box<EvalAltResult>) -> Self {
+        RenderError::Script(err)
+    }
+}
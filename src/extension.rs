@@ -0,0 +1,298 @@
+//! Extensions that bundle Rhai functions, types and constants for the
+//! scripts of a document.
+
+use rhai::{Dynamic, Engine, Module, RhaiNativeFunc};
+use std::any::Any;
+
+/// A named set of Rhai functions, custom types and constants, registered
+/// with a [`Renderer`](crate::renderer::Renderer) or
+/// [`Processor`](crate::processor::Processor) by value.
+///
+/// Unlike a [`CustomFn`](crate::processor::CustomFn) closure, an extension
+/// is an ordinary value, so any state its functions need can be kept in its
+/// fields. Everything it registers comes with a doc string, and
+/// [`DraftsmithExtension::info`] lists it without building a renderer.
+///
+/// ```
+/// use draftsmith_render::extension::{DraftsmithExtension, Registrar};
+/// use draftsmith_render::renderer::Renderer;
+///
+/// struct Currency {
+///     symbol: String,
+/// }
+///
+/// impl DraftsmithExtension for Currency {
+///     fn name(&self) -> &str {
+///         "currency"
+///     }
+///
+///     fn register(&self, registrar: &mut Registrar) {
+///         let symbol = self.symbol.clone();
+///         registrar
+///             .function("money", "Formats an amount of money.", move |amount: i64| {
+///                 format!("{symbol}{amount}")
+///             })
+///             .constant("VAT", "The VAT rate in percent.", 20_i64);
+///     }
+/// }
+///
+/// let currency = Currency { symbol: "€".to_string() };
+/// assert_eq!(currency.info().functions[0].name, "money");
+///
+/// let renderer = Renderer::default().with_extension(currency);
+/// assert_eq!(renderer.process("λ#(money(VAT))#").output, "€20");
+/// ```
+pub trait DraftsmithExtension: Send + Sync {
+    /// The name tools list the extension under.
+    fn name(&self) -> &str;
+
+    /// A short description of the extension.
+    fn description(&self) -> &str {
+        ""
+    }
+
+    /// Registers the extension's functions, types and constants.
+    fn register(&self, registrar: &mut Registrar);
+
+    /// Lists what the extension provides.
+    fn info(&self) -> ExtensionInfo {
+        let mut engine = Engine::new_raw();
+        register_extension(&mut engine, self)
+    }
+}
+
+/// What an extension provides, as listed by [`DraftsmithExtension::info`].
+#[derive(Debug, Clone, Default)]
+pub struct ExtensionInfo {
+    pub name: String,
+    pub description: String,
+    pub functions: Vec<FunctionInfo>,
+    pub types: Vec<TypeInfo>,
+    pub constants: Vec<ConstantInfo>,
+}
+
+/// A Rhai function registered by an extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionInfo {
+    pub name: String,
+    /// The number of parameters the function takes.
+    pub params: usize,
+    pub doc: String,
+}
+
+/// A custom type registered by an extension, under the name `type_of`
+/// returns for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TypeInfo {
+    pub name: String,
+    pub doc: String,
+}
+
+/// A constant registered by an extension.
+#[derive(Debug, Clone)]
+pub struct ConstantInfo {
+    pub name: String,
+    pub value: Dynamic,
+    pub doc: String,
+}
+
+/// Registers the parts of an extension with a Rhai engine and records them.
+pub struct Registrar<'a> {
+    engine: &'a mut Engine,
+    constants: Module,
+    info: ExtensionInfo,
+}
+
+impl Registrar<'_> {
+    /// Registers a Rhai function. Register it again under the same name for
+    /// another overload, or with a custom type as the first parameter for a
+    /// method.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name scripts call the function by.
+    /// * `doc` - What the function does.
+    /// * `func` - The function or closure.
+    pub fn function<
+        A: 'static,
+        const N: usize,
+        const X: bool,
+        R: Any + Clone + Send + Sync,
+        const F: bool,
+    >(
+        &mut self,
+        name: &str,
+        doc: &str,
+        func: impl RhaiNativeFunc<A, N, X, R, F> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.engine.register_fn(name, func);
+        self.info.functions.push(FunctionInfo {
+            name: name.to_string(),
+            params: N,
+            doc: doc.to_string(),
+        });
+        self
+    }
+
+    /// Registers a custom type, so that its values can be passed to and
+    /// returned from the extension's functions.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name `type_of` returns for values of the type.
+    /// * `doc` - What the type represents.
+    pub fn custom_type<T: Any + Clone + Send + Sync>(
+        &mut self,
+        name: &str,
+        doc: &str,
+    ) -> &mut Self {
+        self.engine.register_type_with_name::<T>(name);
+        self.info.types.push(TypeInfo {
+            name: name.to_string(),
+            doc: doc.to_string(),
+        });
+        self
+    }
+
+    /// Registers a constant that every chunk and inline expression can read.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name of the constant.
+    /// * `doc` - What the constant means.
+    /// * `value` - The value of the constant.
+    pub fn constant(
+        &mut self,
+        name: &str,
+        doc: &str,
+        value: impl Any + Clone + Send + Sync,
+    ) -> &mut Self {
+        let value = Dynamic::from(value);
+        self.constants.set_var(name, value.clone());
+        self.info.constants.push(ConstantInfo {
+            name: name.to_string(),
+            value,
+            doc: doc.to_string(),
+        });
+        self
+    }
+
+    /// The engine itself, for anything the other methods do not cover, such
+    /// as getters or type iterators. What is registered here is not listed
+    /// by [`DraftsmithExtension::info`].
+    pub fn engine(&mut self) -> &mut Engine {
+        self.engine
+    }
+}
+
+/// Registers an extension with a Rhai engine.
+///
+/// # Returns
+///
+/// What the extension registered.
+pub(crate) fn register_extension<E: DraftsmithExtension + ?Sized>(
+    engine: &mut Engine,
+    extension: &E,
+) -> ExtensionInfo {
+    let mut registrar = Registrar {
+        engine,
+        constants: Module::new(),
+        info: ExtensionInfo {
+            name: extension.name().to_string(),
+            description: extension.description().to_string(),
+            ..ExtensionInfo::default()
+        },
+    };
+    extension.register(&mut registrar);
+
+    // Variables of a global module can be read by every script
+    let Registrar {
+        engine,
+        constants,
+        info,
+    } = registrar;
+    if !info.constants.is_empty() {
+        engine.register_global_module(constants.into());
+    }
+    info
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::EngineSettings;
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct Point {
+        x: i64,
+        y: i64,
+    }
+
+    struct Geometry {
+        scale: i64,
+    }
+
+    impl DraftsmithExtension for Geometry {
+        fn name(&self) -> &str {
+            "geometry"
+        }
+
+        fn description(&self) -> &str {
+            "Points on a grid."
+        }
+
+        fn register(&self, registrar: &mut Registrar) {
+            let scale = self.scale;
+            registrar
+                .custom_type::<Point>("Point", "A point on the grid.")
+                .function("point", "Creates a scaled point.", move |x: i64, y: i64| {
+                    Point {
+                        x: x * scale,
+                        y: y * scale,
+                    }
+                })
+                .function("norm", "The Manhattan norm of a point.", |p: &mut Point| {
+                    p.x.abs() + p.y.abs()
+                })
+                .constant("ORIGIN", "The origin of the grid.", Point { x: 0, y: 0 });
+        }
+    }
+
+    #[test]
+    fn test_extensions() {
+        let extension = Geometry { scale: 10 };
+        let info = extension.info();
+        assert_eq!(info.name, "geometry");
+        assert_eq!(info.description, "Points on a grid.");
+        assert_eq!(
+            info.functions,
+            [
+                FunctionInfo {
+                    name: "point".to_string(),
+                    params: 2,
+                    doc: "Creates a scaled point.".to_string(),
+                },
+                FunctionInfo {
+                    name: "norm".to_string(),
+                    params: 1,
+                    doc: "The Manhattan norm of a point.".to_string(),
+                },
+            ]
+        );
+        assert_eq!(info.types[0].name, "Point");
+        assert_eq!(info.constants[0].name, "ORIGIN");
+        assert_eq!(
+            info.constants[0].value.clone().cast::<Point>(),
+            Point { x: 0, y: 0 }
+        );
+
+        let engine = EngineSettings {
+            extensions: vec![Box::new(extension)],
+            ..EngineSettings::default()
+        }
+        .build();
+        assert_eq!(engine.eval::<i64>("point(1, -2).norm()").unwrap(), 30);
+        assert_eq!(engine.eval::<i64>("norm(ORIGIN)").unwrap(), 0);
+        assert_eq!(engine.eval::<String>("type_of(ORIGIN)").unwrap(), "Point");
+    }
+}
@@ -1,42 +1,25 @@
+pub mod admonition;
+pub mod alert;
+pub mod diagnostic;
+pub mod error;
+pub mod extension;
+pub mod options;
+pub mod parser;
 pub mod processor;
+pub mod renderer;
+pub mod script;
+pub mod style;
 
-use comrak::nodes::NodeValue;
-// https://raw.githubusercontent.com/kivikakk/comrak/f4853af61978e90d73f3b8c9a63be186d85c1e5c/examples/syntect.rs
-use comrak::plugins::syntect::SyntectAdapterBuilder;
-use comrak::{format_html_with_plugins, parse_document, Arena, Options, Plugins};
-use processor::{CustomFn, Processor};
+use error::RenderError;
+use options::RenderOptions;
+use processor::{CustomFn, Processor, Rendered};
+use renderer::Renderer;
 
 pub fn add(left: u64, right: u64) -> u64 {
     left + right
 }
 
-/// Configures the Comrak options for Markdown parsing and rendering.
-///
-/// This function sets various extension and rendering options to customize
-/// the behavior of the Markdown parser and HTML renderer.
-///
-/// # Arguments
-///
-/// * `options` - A mutable reference to a Comrak `Options` struct.
-fn config_opts(options: &mut Options) {
-    options.extension.autolink = true;
-    options.extension.footnotes = true;
-    options.extension.greentext = true;
-    options.extension.math_dollars = true;
-    options.extension.spoiler = true;
-    options.extension.strikethrough = true;
-    options.extension.table = true;
-    options.extension.tagfilter = false;
-    options.extension.tasklist = true;
-    options.extension.underline = true;
-    options.extension.wikilinks_title_after_pipe = true;
-    options.render.figure_with_caption = true;
-    options.render.full_info_string = true;
-    options.render.github_pre_lang = true;
-    options.render.unsafe_ = true;
-}
-
-pub fn process_md(document: &str, functions: Option<Vec<CustomFn>>) -> String {
+pub fn process_md(document: &str, functions: Option<Vec<CustomFn>>) -> Rendered {
     let mut processor = Processor::new(functions);
     processor.process(document)
 }
@@ -45,86 +28,57 @@ pub fn process_md(document: &str, functions: Option<Vec<CustomFn>>) -> String {
 ///
 /// This function takes a Markdown string as input, processes it using custom rules,
 /// and then converts it to HTML using the Comrak library with customized options.
+/// It sets up a new [`Renderer`] for every call; keep a `Renderer` around
+/// when converting many documents.
 ///
 /// # Arguments
 ///
 /// * `document` - A string slice that holds the Markdown content to be parsed.
+/// * `functions` - Optional Rhai function registrations.
+/// * `options` - The markdown extensions and rendering flags to use.
 ///
 /// # Returns
 ///
-/// A `String` containing the parsed and formatted HTML output.
-///
-/// # Process
-///
-/// 1. Creates an Arena for memory management.
-/// 2. Configures Comrak options using `config_opts`.
-/// 3. Preprocesses the document using a custom `Processor`.
-/// 4. Parses the preprocessed document into an AST.
-/// 5. Iterates over the AST, applying custom transformations (e.g., for math elements).
-/// 6. Formats the modified AST into HTML.
+/// A [`Rendered`] containing the parsed and formatted HTML output, and the
+/// diagnostics raised while preprocessing the document.
 ///
 /// # Note
 ///
 /// This function uses unsafe Rust features through the Comrak library's options.
-pub fn parse_md_to_html(document: &str, functions: Option<Vec<CustomFn>>) -> String {
-    // Set up plugins for syntax highlighting
-    // let adapter = builder.build();
-    let _options = Options::default();
-    let mut plugins = Plugins::default();
-
-    // The returned nodes are created in the supplied Arena, and are bound by its lifetime.
-    let arena = Arena::new();
-
-    // Configure the options
-    let mut options = Options::default();
-    config_opts(&mut options);
-
-    // Preprocess the document
-    let document = process_md(document, functions);
-    let document = document.as_str();
-
-    // get the AST
-    let root = parse_document(&arena, document, &options);
-
-    // Iterate over all the descendants of root.
-    for node in root.descendants() {
-        /*
-        // Left in for reference
-        if let NodeValue::Text(ref mut text) = node.data.borrow_mut().value {
-            // If the node is a text node, perform the string replacement.
-            *text = text.replace(orig_string, replacement);
-        }
-        */
-
-        // handle math (efficiently)
-        if let NodeValue::Math(ref mut math) = node.data.borrow_mut().value {
-            // Capture the current math literal
-            let math_literal = &mut math.literal;
-
-            // Determine the appropriate prefix and calculate the total length upfront
-            let prefix = if math.display_math { "$$" } else { "$" };
-            let prefix_len = prefix.len();
-            let total_len = prefix_len + math_literal.len() + prefix_len;
-
-            // Reserve the capacity to avoid multiple allocations
-            math_literal.reserve_exact(total_len - math_literal.len());
-
-            // Use `insert_str` to prepend and append the prefix directly to the literal
-            math_literal.insert_str(0, prefix);
-            math_literal.push_str(prefix);
-        }
-    }
-
-    // println!("{:#?}", root);
-
-    let mut html = vec![];
-    // format_html(root, &options, &mut html).unwrap();
-    let builder = SyntectAdapterBuilder::new().theme("base16-ocean.dark");
-    let adapter = builder.build();
-    plugins.render.codefence_syntax_highlighter = Some(&adapter);
+///
+/// # Panics
+///
+/// Panics if the HTML cannot be formatted, see [`try_parse_md_to_html`] for
+/// a version that returns the error instead.
+pub fn parse_md_to_html(
+    document: &str,
+    functions: Option<Vec<CustomFn>>,
+    options: &RenderOptions,
+) -> Rendered {
+    try_parse_md_to_html(document, functions, options).expect("Failed to render HTML")
+}
 
-    format_html_with_plugins(root, &options, &mut html, &plugins).expect("Failed to format HTML");
-    String::from_utf8(html).unwrap()
+/// Parses a Markdown document and converts it to HTML without panicking.
+///
+/// This behaves like [`parse_md_to_html`] but returns a [`RenderError`] if
+/// the HTML cannot be produced.
+///
+/// # Arguments
+///
+/// * `document` - A string slice that holds the Markdown content to be parsed.
+/// * `functions` - Optional Rhai function registrations.
+/// * `options` - The markdown extensions and rendering flags to use.
+///
+/// # Returns
+///
+/// A [`Rendered`] containing the HTML output and diagnostics, or the error
+/// that stopped rendering.
+pub fn try_parse_md_to_html(
+    document: &str,
+    functions: Option<Vec<CustomFn>>,
+    options: &RenderOptions,
+) -> Result<Rendered, RenderError> {
+    Renderer::new(functions, options.clone()).render(document)
 }
 
 #[cfg(test)]
@@ -145,7 +99,8 @@ mod tests {
         .to_string();
 
         // Create the HTML
-        let result = parse_md_to_html(&test_string, Option::None)
+        let result = parse_md_to_html(&test_string, Option::None, &RenderOptions::default())
+            .output
             .trim_end_matches('\n')
             .to_string();
 
@@ -157,4 +112,37 @@ mod tests {
             "Processor output did not match expected output"
         );
     }
+
+    #[test]
+    fn test_try_parse_md_to_html() {
+        let rendered = try_parse_md_to_html(
+            ":::tip\n\nλ#(1 + 1)#\n\n:::",
+            None,
+            &RenderOptions::default(),
+        )
+        .expect("Rendering should not fail");
+
+        assert_eq!(
+            rendered.output,
+            "<div class=\"admonition tip\">\n<p>2</p>\n</div>\n"
+        );
+        assert!(rendered.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_render_options() {
+        let input = "<script>alert(1)</script>\n\nH^2^O";
+        let defaults = parse_md_to_html(input, None, &RenderOptions::default()).output;
+        let options = RenderOptions::builder()
+            .tagfilter(true)
+            .superscript(true)
+            .build();
+        let filtered = parse_md_to_html(input, None, &options).output;
+
+        assert_eq!(defaults, "<script>alert(1)</script>\n<p>H^2^O</p>\n");
+        assert_eq!(
+            filtered,
+            "&lt;script>alert(1)&lt;/script>\n<p>H<sup>2</sup>O</p>\n"
+        );
+    }
 }
@@ -0,0 +1,254 @@
+//! Options controlling how markdown is parsed and rendered to HTML.
+
+use comrak::Options;
+
+/// The markdown extensions and rendering flags used by
+/// [`parse_md_to_html`](crate::parse_md_to_html).
+///
+/// The defaults match the features Draftsmith has always enabled. Use
+/// [`RenderOptions::builder`] to change them:
+///
+/// ```
+/// use draftsmith_render::options::RenderOptions;
+///
+/// let options = RenderOptions::builder()
+///     .tagfilter(true)
+///     .greentext(false)
+///     .header_ids(Some("h-".to_string()))
+///     .build();
+/// assert!(options.tagfilter);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RenderOptions {
+    /// Turn bare URLs and email addresses into links.
+    pub autolink: bool,
+    /// Enable `<dl>` description lists.
+    pub description_lists: bool,
+    /// Enable `[^1]` footnotes.
+    pub footnotes: bool,
+    /// Treat lines starting with `>` but no space as greentext.
+    pub greentext: bool,
+    /// Add `id` attributes to headings, using this prefix.
+    pub header_ids: Option<String>,
+    /// Enable `$...$` and `$$...$$` math.
+    pub math_dollars: bool,
+    /// Enable ``$`...`$`` math.
+    pub math_code: bool,
+    /// Enable `>>>` fenced block quotes.
+    pub multiline_block_quotes: bool,
+    /// Enable `||spoiler||` text.
+    pub spoiler: bool,
+    /// Enable `~~strikethrough~~`.
+    pub strikethrough: bool,
+    /// Enable `^superscript^`.
+    pub superscript: bool,
+    /// Enable GFM tables.
+    pub table: bool,
+    /// Escape HTML tags that GFM considers unsafe, such as `<script>`.
+    pub tagfilter: bool,
+    /// Enable `- [ ]` task lists.
+    pub tasklist: bool,
+    /// Enable `__underline__`.
+    pub underline: bool,
+    /// Enable `[[url|title]]` wikilinks.
+    pub wikilinks_title_after_pipe: bool,
+    /// Enable `[[title|url]]` wikilinks.
+    pub wikilinks_title_before_pipe: bool,
+    /// Skip front matter delimited by this string, e.g. `---`.
+    pub front_matter_delimiter: Option<String>,
+    /// Convert straight quotes and dashes to typographic ones.
+    pub smart: bool,
+    /// Render soft line breaks as `<br>`.
+    pub hardbreaks: bool,
+    /// Render images with a title as `<figure>` with a caption.
+    pub figure_with_caption: bool,
+    /// Keep the whole info string of code blocks in a `data-meta` attribute.
+    pub full_info_string: bool,
+    /// Use GitHub's `<pre lang="...">` form for code blocks.
+    pub github_pre_lang: bool,
+    /// Pass raw HTML and dangerous URLs through unchanged.
+    pub unsafe_: bool,
+    /// Escape raw HTML instead of removing or passing it through.
+    pub escape: bool,
+    /// Add `data-sourcepos` attributes to block elements.
+    pub sourcepos: bool,
+    /// Render `> [!NOTE]` block quotes as admonitions, see
+    /// [`GITHUB_ALERTS`](crate::alert::GITHUB_ALERTS).
+    pub github_alerts: bool,
+    /// Render Obsidian callouts (`> [!tip]- Title`) as admonitions, see
+    /// [`OBSIDIAN_CALLOUTS`](crate::alert::OBSIDIAN_CALLOUTS). This also
+    /// covers GitHub alerts.
+    pub obsidian_callouts: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            autolink: true,
+            description_lists: false,
+            footnotes: true,
+            greentext: true,
+            header_ids: None,
+            math_dollars: true,
+            math_code: false,
+            multiline_block_quotes: false,
+            spoiler: true,
+            strikethrough: true,
+            superscript: false,
+            table: true,
+            tagfilter: false,
+            tasklist: true,
+            underline: true,
+            wikilinks_title_after_pipe: true,
+            wikilinks_title_before_pipe: false,
+            front_matter_delimiter: None,
+            smart: false,
+            hardbreaks: false,
+            figure_with_caption: true,
+            full_info_string: true,
+            github_pre_lang: true,
+            unsafe_: true,
+            escape: false,
+            sourcepos: false,
+            github_alerts: true,
+            obsidian_callouts: false,
+        }
+    }
+}
+
+impl RenderOptions {
+    /// Starts building options from the defaults.
+    pub fn builder() -> RenderOptionsBuilder {
+        RenderOptionsBuilder::default()
+    }
+
+    /// Builds the Comrak options for these settings.
+    pub(crate) fn comrak_options(&self) -> Options<'static> {
+        let mut options = Options::default();
+        options.extension.autolink = self.autolink;
+        options.extension.description_lists = self.description_lists;
+        options.extension.footnotes = self.footnotes;
+        options.extension.greentext = self.greentext;
+        options.extension.header_ids = self.header_ids.clone();
+        options.extension.math_dollars = self.math_dollars;
+        options.extension.math_code = self.math_code;
+        options.extension.multiline_block_quotes = self.multiline_block_quotes;
+        options.extension.spoiler = self.spoiler;
+        options.extension.strikethrough = self.strikethrough;
+        options.extension.superscript = self.superscript;
+        options.extension.table = self.table;
+        options.extension.tagfilter = self.tagfilter;
+        options.extension.tasklist = self.tasklist;
+        options.extension.underline = self.underline;
+        options.extension.wikilinks_title_after_pipe = self.wikilinks_title_after_pipe;
+        options.extension.wikilinks_title_before_pipe = self.wikilinks_title_before_pipe;
+        options.extension.front_matter_delimiter = self.front_matter_delimiter.clone();
+        options.parse.smart = self.smart;
+        options.render.hardbreaks = self.hardbreaks;
+        options.render.figure_with_caption = self.figure_with_caption;
+        options.render.full_info_string = self.full_info_string;
+        options.render.github_pre_lang = self.github_pre_lang;
+        options.render.unsafe_ = self.unsafe_;
+        options.render.escape = self.escape;
+        options.render.sourcepos = self.sourcepos;
+        options
+    }
+}
+
+/// Builds [`RenderOptions`], starting from the defaults.
+#[derive(Debug, Clone, Default)]
+pub struct RenderOptionsBuilder {
+    options: RenderOptions,
+}
+
+/// Generates a chainable setter for each listed field of [`RenderOptions`].
+macro_rules! setters {
+    ($($field:ident: $ty:ty),* $(,)?) => {
+        $(
+            #[doc = concat!("Sets [`RenderOptions::", stringify!($field), "`].")]
+            pub fn $field(mut self, value: $ty) -> Self {
+                self.options.$field = value;
+                self
+            }
+        )*
+    };
+}
+
+impl RenderOptionsBuilder {
+    setters! {
+        autolink: bool,
+        description_lists: bool,
+        footnotes: bool,
+        greentext: bool,
+        header_ids: Option<String>,
+        math_dollars: bool,
+        math_code: bool,
+        multiline_block_quotes: bool,
+        spoiler: bool,
+        strikethrough: bool,
+        superscript: bool,
+        table: bool,
+        tagfilter: bool,
+        tasklist: bool,
+        underline: bool,
+        wikilinks_title_after_pipe: bool,
+        wikilinks_title_before_pipe: bool,
+        front_matter_delimiter: Option<String>,
+        smart: bool,
+        hardbreaks: bool,
+        figure_with_caption: bool,
+        full_info_string: bool,
+        github_pre_lang: bool,
+        unsafe_: bool,
+        escape: bool,
+        sourcepos: bool,
+        github_alerts: bool,
+        obsidian_callouts: bool,
+    }
+
+    /// Finishes building the options.
+    pub fn build(self) -> RenderOptions {
+        self.options
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builder_overrides_defaults() {
+        let options = RenderOptions::builder()
+            .tagfilter(true)
+            .greentext(false)
+            .superscript(true)
+            .build();
+
+        assert!(options.tagfilter);
+        assert!(!options.greentext);
+        assert!(options.superscript);
+        assert_eq!(
+            RenderOptions {
+                tagfilter: false,
+                greentext: true,
+                superscript: false,
+                ..options
+            },
+            RenderOptions::default()
+        );
+    }
+
+    #[test]
+    fn test_comrak_options() {
+        let options = RenderOptions::builder()
+            .header_ids(Some("h-".to_string()))
+            .description_lists(true)
+            .build()
+            .comrak_options();
+
+        assert_eq!(options.extension.header_ids.as_deref(), Some("h-"));
+        assert!(options.extension.description_lists);
+        assert!(options.render.unsafe_);
+        assert!(!options.extension.tagfilter);
+    }
+}
@@ -0,0 +1,1354 @@
+//! This module parses Draftsmith markdown into a tree of directives,
+//! Rhai chunks, code blocks and lines of text.
+//!
+//! The parser only understands the Draftsmith extensions; everything else is
+//! kept verbatim so that it can be handed on to a CommonMark renderer.
+//! Pandoc fenced divs and MyST directives can be read as well, see
+//! [`Syntax`].
+
+use std::fmt;
+use std::str::FromStr;
+
+/// The directive syntax a document is written in.
+///
+/// Every syntax also accepts Draftsmith's own `:::name` blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    /// `:::name Title {#id .class}` blocks closed by `:::`.
+    #[default]
+    Draftsmith,
+    /// Pandoc fenced divs, `::: warning` or `::: {.warning #id}`, closed by a
+    /// line of three or more colons.
+    Pandoc,
+    /// MyST directives, ```` ```{note} Title ```` or `:::{note} Title`,
+    /// followed by `:option: value` lines. Like a code fence, a directive is
+    /// closed by a line of at least as many colons or backticks, so
+    /// `::::{note}` can hold a `:::{tip}`.
+    Myst,
+}
+
+/// A region of the source document.
+///
+/// `start` and `end` are byte offsets, `line` and `column` are the 1-based
+/// position of `start`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A parsed document.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Document {
+    pub blocks: Vec<Block>,
+}
+
+/// A block level element of the document.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Block {
+    /// A line of ordinary markdown.
+    Line(Line),
+    /// A `:::name` block and everything up to its matching `:::`.
+    Directive(Directive),
+    /// A ```` ```{rhai} ```` or ```` ```{rhai-display} ```` chunk.
+    Chunk(Chunk),
+    /// An ordinary fenced code block.
+    CodeBlock(CodeBlock),
+    /// A `:::` without a directive to close.
+    UnmatchedClose(Span),
+}
+
+/// A single line of text, split into literal text and inline expressions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Line {
+    pub text: String,
+    pub inlines: Vec<Inline>,
+    pub span: Span,
+}
+
+/// A piece of a line.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inline {
+    /// Literal text.
+    Text(String),
+    /// A `λ#(...)#` expression, `span` covers the delimiters. The code may
+    /// span several lines.
+    Expr { code: String, span: Span },
+}
+
+/// A `{#id .class key=value}` attribute block.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Attributes {
+    pub id: Option<String>,
+    pub classes: Vec<String>,
+    /// Other attributes in the order they were written. A bare `key` has an
+    /// empty value.
+    pub pairs: Vec<(String, String)>,
+}
+
+impl Attributes {
+    /// Returns true if no attributes were given.
+    pub fn is_empty(&self) -> bool {
+        self.id.is_none() && self.classes.is_empty() && self.pairs.is_empty()
+    }
+
+    /// The value of the attribute `key`, if it was given.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Parses the inside of an attribute block, without the braces.
+    ///
+    /// # Returns
+    ///
+    /// The attributes, or `None` if the text is not a valid attribute block.
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut attributes = Self::default();
+        let mut chars = text.trim().chars().peekable();
+
+        while let Some(&c) = chars.peek() {
+            if c.is_whitespace() {
+                chars.next();
+                continue;
+            }
+            let mut token = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() {
+                    break;
+                }
+                chars.next();
+                if c == '"' {
+                    // A quoted value runs to the next quote, spaces included
+                    loop {
+                        match chars.next()? {
+                            '"' => break,
+                            c => token.push(c),
+                        }
+                    }
+                } else {
+                    token.push(c);
+                }
+            }
+
+            if let Some(id) = token.strip_prefix('#') {
+                attributes.id = Some(id.to_string());
+            } else if let Some(class) = token.strip_prefix('.') {
+                attributes.classes.push(class.to_string());
+            } else {
+                let (key, value) = token.split_once('=').unwrap_or((&token, ""));
+                if key.is_empty()
+                    || !key
+                        .chars()
+                        .all(|c| c.is_alphanumeric() || "-_:".contains(c))
+                {
+                    return None;
+                }
+                attributes.pairs.push((key.to_string(), value.to_string()));
+            }
+        }
+        Some(attributes)
+    }
+}
+
+/// Splits a trailing `{...}` attribute block off the text of a line.
+///
+/// # Returns
+///
+/// The text before the block, trimmed, and the attributes. Text that does
+/// not end with a valid attribute block is returned unchanged.
+pub fn split_attributes(text: &str) -> (&str, Attributes) {
+    if let (Some(body), Some(start)) = (text.strip_suffix('}'), text.rfind('{')) {
+        if let Some(attributes) = Attributes::parse(&body[start + 1..]) {
+            return (text[..start].trim_end(), attributes);
+        }
+    }
+    (text, Attributes::default())
+}
+
+/// A `:::name` block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Directive {
+    pub name: String,
+    /// The text after the name on the opening line, e.g. a title.
+    pub args: String,
+    /// The attribute block at the end of the opening line.
+    pub attributes: Attributes,
+    pub children: Vec<Block>,
+    /// The whole block, from the opening line to the closing `:::`.
+    pub span: Span,
+    /// The opening `:::name` line.
+    pub open_span: Span,
+    /// Whether a closing `:::` was found before the end of the document.
+    pub closed: bool,
+    /// The indentation or `>` markers before the opening line, relative to
+    /// the enclosing directive, e.g. inside a list item or a block quote.
+    /// Its lines, which are given without them, are rendered with them.
+    pub prefix: String,
+}
+
+/// Whether a Rhai chunk shows its source and result or only runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkKind {
+    Hidden,
+    Display,
+}
+
+/// A Rhai code chunk.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    pub kind: ChunkKind,
+    pub code: String,
+    pub span: Span,
+    /// The 1-based line number of the first line of code.
+    pub code_line: usize,
+    pub closed: bool,
+    /// The indentation or `>` markers before the opening fence, which the
+    /// output is rendered with.
+    pub prefix: String,
+}
+
+/// An ordinary fenced code block, kept verbatim.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CodeBlock {
+    pub open: Line,
+    pub lines: Vec<Line>,
+    pub close: Option<Line>,
+    pub span: Span,
+    /// Whether the opening fence ends with `{expand}`, so that inline
+    /// expressions in the block are evaluated.
+    pub expand: bool,
+}
+
+impl Syntax {
+    /// All syntaxes, in the order they are documented.
+    pub const ALL: [Syntax; 3] = [Syntax::Draftsmith, Syntax::Pandoc, Syntax::Myst];
+
+    /// The name used to select the syntax, e.g. on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Syntax::Draftsmith => "draftsmith",
+            Syntax::Pandoc => "pandoc",
+            Syntax::Myst => "myst",
+        }
+    }
+}
+
+impl fmt::Display for Syntax {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Syntax::ALL
+            .into_iter()
+            .find(|syntax| syntax.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = Syntax::ALL.iter().map(|s| s.name()).collect();
+                format!(
+                    "unknown syntax `{}`, expected one of: {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+const DIRECTIVE_MARKER: &str = ":::";
+/// The shortest run of backticks or tildes that opens a code block.
+const MIN_FENCE_LEN: usize = 3;
+/// How much further than its opening fence a closing fence may be indented.
+const MAX_FENCE_INDENT: usize = 3;
+const LAMBDA_OPEN: &str = "λ#(";
+const LAMBDA_CLOSE: &str = ")#";
+/// Written before an opening delimiter to keep it as text.
+const LAMBDA_ESCAPE: char = '\\';
+/// The attribute block that turns on inline expressions in a code block.
+pub(crate) const EXPAND_ATTRIBUTE: &str = "{expand}";
+
+/// The admonition kinds of MyST and pandoc documents and the built-in
+/// admonitions they are rendered as, matching the GitHub alerts and
+/// Obsidian callouts of the same name in [`crate::alert`]. A kind is only
+/// rendered as its alias if no admonition is registered under its own name.
+pub const ADMONITION_ALIASES: [(&str, &str); 7] = [
+    ("note", "info"),
+    ("important", "alert"),
+    ("caution", "error"),
+    ("danger", "error"),
+    ("hint", "tip"),
+    ("attention", "warning"),
+    ("seealso", "info"),
+];
+
+/// The delimiters around inline expressions, `λ#(` and `)#` by default.
+///
+/// ```
+/// use draftsmith_render::parser::{parse_line, Delimiters, Inline, Span};
+///
+/// let line = parse_line("Total: `r 1 + 1`", Span::default(), &Delimiters::rmarkdown());
+/// assert!(matches!(&line.inlines[1], Inline::Expr { code, .. } if code == "1 + 1"));
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Delimiters {
+    open: String,
+    close: String,
+}
+
+impl Delimiters {
+    /// Creates delimiters from the text before and after an expression.
+    ///
+    /// # Returns
+    ///
+    /// The delimiters, or an error if either of them is empty.
+    pub fn new(open: impl Into<String>, close: impl Into<String>) -> Result<Self, String> {
+        let (open, close) = (open.into(), close.into());
+        if open.is_empty() || close.is_empty() {
+            return Err("expression delimiters cannot be empty".to_string());
+        }
+        Ok(Self { open, close })
+    }
+
+    /// R Markdown's `` `r expr` ``.
+    pub fn rmarkdown() -> Self {
+        Self {
+            open: "`r ".to_string(),
+            close: "`".to_string(),
+        }
+    }
+
+    /// Template-style `{{ expr }}`.
+    pub fn braces() -> Self {
+        Self {
+            open: "{{".to_string(),
+            close: "}}".to_string(),
+        }
+    }
+
+    /// The text before an expression.
+    pub fn open(&self) -> &str {
+        &self.open
+    }
+
+    /// The text after an expression.
+    pub fn close(&self) -> &str {
+        &self.close
+    }
+}
+
+impl Default for Delimiters {
+    fn default() -> Self {
+        Self {
+            open: LAMBDA_OPEN.to_string(),
+            close: LAMBDA_CLOSE.to_string(),
+        }
+    }
+}
+
+/// The opening line of a directive.
+struct Opening {
+    name: String,
+    args: String,
+    attributes: Attributes,
+    /// The code fence of a MyST directive, which closes it.
+    fence: Option<Fence>,
+    /// Whether `:option: value` lines may follow.
+    options: bool,
+    /// The number of colons on the opening line; only a run at least as
+    /// long closes the directive.
+    colons: usize,
+}
+
+impl Opening {
+    /// An opening line with `args` after the name, which may end with an
+    /// attribute block.
+    fn new(name: &str, args: &str) -> Self {
+        let (args, attributes) = split_attributes(args.trim());
+        Self {
+            name: name.to_string(),
+            args: args.to_string(),
+            attributes,
+            fence: None,
+            options: false,
+            colons: DIRECTIVE_MARKER.len(),
+        }
+    }
+}
+
+/// The meaning of a line outside of any code block.
+enum LineKind {
+    DirectiveStart(Opening),
+    /// A line of colons, with their number.
+    DirectiveEnd(usize),
+    ChunkStart(ChunkKind, Fence),
+    FenceStart {
+        fence: Fence,
+        expand: bool,
+    },
+    Text,
+}
+
+/// The opening fence of a code block, following the CommonMark rules.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Fence {
+    /// `` ` `` or `~`.
+    marker: char,
+    len: usize,
+    /// The indentation and `>` markers before the fence.
+    prefix: String,
+}
+
+impl Fence {
+    /// Reads an opening code fence.
+    ///
+    /// # Returns
+    ///
+    /// The fence and its trimmed info string, or `None` if the line does not
+    /// open a code block.
+    fn open(line: &str) -> Option<(Self, &str)> {
+        let (prefix, trimmed) = split_container(line);
+        let marker = trimmed.chars().next().filter(|c| matches!(c, '`' | '~'))?;
+        let len = trimmed.len() - trimmed.trim_start_matches(marker).len();
+        let info = trimmed[len..].trim();
+        // A backtick in the info string makes it a code span instead
+        if len < MIN_FENCE_LEN || (marker == '`' && info.contains('`')) {
+            return None;
+        }
+        let fence = Fence {
+            marker,
+            len,
+            prefix: prefix.to_string(),
+        };
+        Some((fence, info))
+    }
+
+    /// Returns true if `line` closes the code block this fence opened: a run
+    /// of the same character, at least as long, with nothing after it.
+    fn is_closed_by(&self, line: &str) -> bool {
+        let line = strip_container(line, &self.prefix);
+        let trimmed = line.trim_start();
+        let len = trimmed.len() - trimmed.trim_start_matches(self.marker).len();
+        len >= self.len
+            && trimmed[len..].trim().is_empty()
+            && line.len() - trimmed.len() <= MAX_FENCE_INDENT
+    }
+}
+
+/// Splits the indentation and `>` markers of list items and block quotes
+/// off the start of a line.
+fn split_container(line: &str) -> (&str, &str) {
+    let rest = line.trim_start_matches(|c: char| c.is_whitespace() || c == '>');
+    line.split_at(line.len() - rest.len())
+}
+
+/// Removes the `prefix` of an enclosing block from the start of a line.
+///
+/// Lines may be indented less than the prefix, and a lazy continuation line
+/// without the `>` of a block quote keeps the rest of the line.
+fn strip_container<'l>(line: &'l str, prefix: &str) -> &'l str {
+    let mut rest = line;
+    for c in prefix.chars() {
+        match rest.strip_prefix(c) {
+            Some(stripped) => rest = stripped,
+            None if c.is_whitespace() => {}
+            None => break,
+        }
+    }
+    rest
+}
+
+/// Classifies a line of text outside of a code block.
+fn classify(line: &str, syntax: Syntax) -> LineKind {
+    if let Some((fence, info)) = Fence::open(line) {
+        return match info {
+            "{rhai}" => LineKind::ChunkStart(ChunkKind::Hidden, fence),
+            "{rhai-display}" => LineKind::ChunkStart(ChunkKind::Display, fence),
+            _ if syntax == Syntax::Myst => match myst_directive(info) {
+                Some(opening) => LineKind::DirectiveStart(Opening {
+                    fence: Some(fence),
+                    ..opening
+                }),
+                None => LineKind::FenceStart {
+                    fence,
+                    expand: false,
+                },
+            },
+            _ => LineKind::FenceStart {
+                fence,
+                expand: info.ends_with(EXPAND_ATTRIBUTE),
+            },
+        };
+    }
+
+    let (_, trimmed) = split_container(line);
+
+    let colons = trimmed.len() - trimmed.trim_start_matches(':').len();
+    if colons < DIRECTIVE_MARKER.len() {
+        return LineKind::Text;
+    }
+    let header = &trimmed[colons..];
+    if header.trim().is_empty()
+        && (colons == DIRECTIVE_MARKER.len() || syntax != Syntax::Draftsmith)
+    {
+        return LineKind::DirectiveEnd(colons);
+    }
+    let opening = match syntax {
+        Syntax::Pandoc if header.starts_with(|c: char| c.is_whitespace() || c == '{') => {
+            pandoc_div(header)
+        }
+        // More colons let a MyST directive hold others
+        Syntax::Myst if header.starts_with('{') => {
+            myst_directive(header).map(|opening| Opening { colons, ..opening })
+        }
+        _ if colons == DIRECTIVE_MARKER.len() => draftsmith_directive(header),
+        _ => None,
+    };
+    opening.map_or(LineKind::Text, LineKind::DirectiveStart)
+}
+
+/// Reads the rest of a `:::name args` line.
+fn draftsmith_directive(header: &str) -> Option<Opening> {
+    let end = header
+        .find(|c: char| !is_name_char(c))
+        .unwrap_or(header.len());
+    let (name, args) = header.split_at(end);
+    if name.is_empty() || !(args.is_empty() || args.starts_with(char::is_whitespace)) {
+        return None;
+    }
+    Some(Opening::new(name, args))
+}
+
+/// Reads the rest of a pandoc `::: name` or `::: {.name #id}` line.
+///
+/// The first class of an attribute block is the name, which is left empty
+/// if there is none. Trailing colons are ignored.
+fn pandoc_div(header: &str) -> Option<Opening> {
+    let header = header.trim().trim_end_matches(':').trim_end();
+    if let Some(inner) = header.strip_prefix('{').and_then(|h| h.strip_suffix('}')) {
+        let mut attributes = Attributes::parse(inner)?;
+        let name = if attributes.classes.is_empty() {
+            String::new()
+        } else {
+            attributes.classes.remove(0)
+        };
+        if !name.chars().all(is_name_char) {
+            return None;
+        }
+        return Some(Opening {
+            name,
+            args: String::new(),
+            attributes,
+            fence: None,
+            options: false,
+            colons: DIRECTIVE_MARKER.len(),
+        });
+    }
+    let (name, args) = header
+        .split_once(char::is_whitespace)
+        .unwrap_or((header, ""));
+    if name.is_empty() || !name.chars().all(is_name_char) {
+        return None;
+    }
+    Some(Opening::new(name, args))
+}
+
+/// Reads the rest of a MyST `{name} args` line.
+fn myst_directive(header: &str) -> Option<Opening> {
+    let (name, args) = header.strip_prefix('{')?.split_once('}')?;
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_alphanumeric() || "-_:".contains(c))
+    {
+        return None;
+    }
+    Some(Opening {
+        options: true,
+        ..Opening::new(name, args)
+    })
+}
+
+/// Reads a MyST `:key: value` option line.
+fn myst_option(line: &str) -> Option<(&str, &str)> {
+    let (key, value) = line.trim().strip_prefix(':')?.split_once(':')?;
+    if key.is_empty() || !key.chars().all(|c| c.is_alphanumeric() || "-_".contains(c)) {
+        return None;
+    }
+    Some((key, value.trim()))
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '!' | '{' | '}' | '-')
+}
+
+/// A chunk or code block that is still being read, with its opening fence.
+enum Open {
+    Chunk(Chunk, Vec<String>, Fence),
+    Code(CodeBlock, Fence),
+}
+
+/// A directive that is still being read.
+struct Frame {
+    directive: Directive,
+    /// The code fence of a MyST directive.
+    fence: Option<Fence>,
+    /// Whether `:option: value` lines are still accepted.
+    options: bool,
+    /// The shortest line of colons that closes the directive.
+    colons: usize,
+    /// The prefix of the directive's lines, including those of the
+    /// directives around it.
+    prefix: String,
+}
+
+/// Builds a [`Document`] from source text one line at a time.
+struct Parser<'d> {
+    document: Document,
+    stack: Vec<Frame>,
+    open: Option<Open>,
+    syntax: Syntax,
+    delimiters: &'d Delimiters,
+    /// Lines holding an inline expression that is not closed yet.
+    pending: Option<(String, Span)>,
+}
+
+/// Parses a document written with Draftsmith's own syntax.
+///
+/// # Arguments
+///
+/// * `input` - The markdown source.
+///
+/// # Returns
+///
+/// The [`Document`] tree. Parsing never fails; unclosed blocks are closed at
+/// the end of the input and stray `:::` lines are kept as
+/// [`Block::UnmatchedClose`].
+pub fn parse(input: &str) -> Document {
+    parse_with_syntax(input, Syntax::default())
+}
+
+/// Parses a document, also accepting the directives of `syntax`.
+///
+/// # Arguments
+///
+/// * `input` - The markdown source.
+/// * `syntax` - The directive syntax the document is written in.
+pub fn parse_with_syntax(input: &str, syntax: Syntax) -> Document {
+    parse_with(input, syntax, &Delimiters::default())
+}
+
+/// Parses a document, also accepting the directives of `syntax` and using
+/// `delimiters` for inline expressions.
+///
+/// # Arguments
+///
+/// * `input` - The markdown source.
+/// * `syntax` - The directive syntax the document is written in.
+/// * `delimiters` - The delimiters around inline expressions.
+pub fn parse_with(input: &str, syntax: Syntax, delimiters: &Delimiters) -> Document {
+    let mut parser = Parser {
+        document: Document::default(),
+        stack: Vec::new(),
+        open: None,
+        syntax,
+        delimiters,
+        pending: None,
+    };
+
+    let mut offset = 0;
+    for (index, raw) in input.split_inclusive('\n').enumerate() {
+        let text = raw.trim_end_matches('\n').trim_end_matches('\r');
+        let span = Span {
+            start: offset,
+            end: offset + text.len(),
+            line: index + 1,
+            column: 1,
+        };
+        parser.line(text, span);
+        offset += raw.len();
+    }
+
+    parser.finish(offset)
+}
+
+impl Parser<'_> {
+    fn line(&mut self, line: &str, span: Span) {
+        // Lines inside a directive are read without the directive's prefix
+        let text = match self.stack.last() {
+            Some(frame) => strip_container(line, &frame.prefix),
+            None => line,
+        };
+        let prefix = &line[..line.len() - text.len()];
+        let span = Span {
+            start: span.start + prefix.len(),
+            column: span.column + prefix.chars().count(),
+            ..span
+        };
+
+        match self.open.take() {
+            Some(Open::Chunk(mut chunk, mut code, fence)) => {
+                if fence.is_closed_by(text) {
+                    chunk.code = code.join("\n");
+                    chunk.span.end = span.end;
+                    chunk.closed = true;
+                    self.push(Block::Chunk(chunk));
+                } else {
+                    code.push(strip_container(text, &fence.prefix).to_string());
+                    self.open = Some(Open::Chunk(chunk, code, fence));
+                }
+            }
+            Some(Open::Code(mut block, fence)) => {
+                let line = parse_line(text, span, self.delimiters);
+                block.span.end = span.end;
+                if fence.is_closed_by(text) {
+                    block.close = Some(line);
+                    self.push(Block::CodeBlock(block));
+                } else {
+                    block.lines.push(line);
+                    self.open = Some(Open::Code(block, fence));
+                }
+            }
+            None => self.outside_code(text, span),
+        }
+    }
+
+    fn outside_code(&mut self, text: &str, span: Span) {
+        if let Some((mut pending, mut pending_span)) = self.pending.take() {
+            // An expression can continue on the following lines, but not past
+            // a blank line or the start of another block
+            if !text.trim().is_empty() && matches!(classify(text, self.syntax), LineKind::Text) {
+                pending.push('\n');
+                pending.push_str(text);
+                pending_span.end = span.end;
+                self.text(pending, pending_span);
+                return;
+            }
+            self.flush_pending(pending, pending_span);
+        }
+
+        if let Some(frame) = self.stack.last_mut() {
+            if frame.options {
+                if let Some((key, value)) = myst_option(text) {
+                    let attributes = &mut frame.directive.attributes;
+                    match key {
+                        "class" => attributes
+                            .classes
+                            .extend(value.split_whitespace().map(str::to_string)),
+                        "name" => attributes.id = Some(value.to_string()),
+                        _ => attributes.pairs.push((key.to_string(), value.to_string())),
+                    }
+                    return;
+                }
+                frame.options = false;
+            }
+            if let Some(fence) = &frame.fence {
+                if fence.is_closed_by(text) {
+                    self.close(span.end, true);
+                    return;
+                }
+            }
+        }
+
+        match classify(text, self.syntax) {
+            LineKind::DirectiveStart(opening) => {
+                let (prefix, _) = split_container(text);
+                let outer = self.stack.last().map_or("", |frame| &frame.prefix);
+                let frame = Frame {
+                    prefix: format!("{}{}", outer, prefix),
+                    directive: Directive {
+                        name: opening.name,
+                        args: opening.args,
+                        attributes: opening.attributes,
+                        children: Vec::new(),
+                        span,
+                        open_span: span,
+                        closed: false,
+                        prefix: prefix.to_string(),
+                    },
+                    fence: opening.fence,
+                    options: opening.options,
+                    colons: opening.colons,
+                };
+                self.stack.push(frame);
+            }
+            // A MyST code fence directive can only be closed by a fence, and
+            // a shorter line of colons than the opening one is only text
+            LineKind::DirectiveEnd(colons) => match self.stack.last() {
+                Some(frame) if frame.fence.is_none() && colons >= frame.colons => {
+                    self.close(span.end, true)
+                }
+                Some(frame) if frame.fence.is_none() => self.text(text.to_string(), span),
+                _ => self.push(Block::UnmatchedClose(span)),
+            },
+            LineKind::ChunkStart(kind, fence) => {
+                let chunk = Chunk {
+                    kind,
+                    code: String::new(),
+                    span,
+                    code_line: span.line + 1,
+                    closed: false,
+                    prefix: fence.prefix.clone(),
+                };
+                self.open = Some(Open::Chunk(chunk, Vec::new(), fence));
+            }
+            LineKind::FenceStart { fence, expand } => {
+                let block = CodeBlock {
+                    open: parse_line(text, span, self.delimiters),
+                    lines: Vec::new(),
+                    close: None,
+                    span,
+                    expand,
+                };
+                self.open = Some(Open::Code(block, fence));
+            }
+            LineKind::Text => self.text(text.to_string(), span),
+        }
+    }
+
+    /// Adds text, holding it back while it ends in an unclosed expression.
+    fn text(&mut self, text: String, span: Span) {
+        let (inlines, unclosed) = split_inlines(&text, span, self.delimiters);
+        if unclosed {
+            self.pending = Some((text, span));
+        } else {
+            self.push(Block::Line(Line {
+                text,
+                inlines,
+                span,
+            }));
+        }
+    }
+
+    /// Adds held back text whose expression was never closed, keeping the
+    /// opening delimiter as text.
+    fn flush_pending(&mut self, text: String, span: Span) {
+        let line = parse_line(&text, span, self.delimiters);
+        self.push(Block::Line(line));
+    }
+
+    /// Appends a finished block to the innermost open directive.
+    fn push(&mut self, block: Block) {
+        match self.stack.last_mut() {
+            Some(frame) => frame.directive.children.push(block),
+            None => self.document.blocks.push(block),
+        }
+    }
+
+    /// Closes the innermost open directive.
+    ///
+    /// # Arguments
+    ///
+    /// * `end` - The byte offset the directive ends at.
+    /// * `closed` - Whether it was closed explicitly.
+    fn close(&mut self, end: usize, closed: bool) {
+        let Some(Frame { mut directive, .. }) = self.stack.pop() else {
+            return;
+        };
+        // MyST's generic admonition takes its kind from its class
+        if self.syntax == Syntax::Myst
+            && directive.name == "admonition"
+            && !directive.attributes.classes.is_empty()
+        {
+            directive.name = directive.attributes.classes.remove(0);
+        }
+        directive.span.end = end;
+        directive.closed = closed;
+        self.push(Block::Directive(directive));
+    }
+
+    /// Closes anything left open at the end of the input.
+    fn finish(mut self, end: usize) -> Document {
+        if let Some((text, span)) = self.pending.take() {
+            self.flush_pending(text, span);
+        }
+        match self.open.take() {
+            Some(Open::Chunk(mut chunk, code, _)) => {
+                chunk.code = code.join("\n");
+                chunk.span.end = end;
+                self.push(Block::Chunk(chunk));
+            }
+            Some(Open::Code(block, _)) => self.push(Block::CodeBlock(block)),
+            None => {}
+        }
+        while !self.stack.is_empty() {
+            self.close(end, false);
+        }
+        self.document
+    }
+}
+
+/// Splits a line into text and inline expressions.
+///
+/// Expressions inside `` `code spans` `` are kept as text, and so is an
+/// escaped opening delimiter (`\λ#(`), without its backslash.
+///
+/// # Arguments
+///
+/// * `text` - The line, without its line ending. It may hold several lines
+///   of a multi-line expression.
+/// * `span` - The position of the line in the document.
+/// * `delimiters` - The delimiters around inline expressions.
+///
+/// # Returns
+///
+/// A [`Line`] holding the original text and its inline pieces.
+pub fn parse_line(text: &str, span: Span, delimiters: &Delimiters) -> Line {
+    Line {
+        text: text.to_string(),
+        inlines: split_inlines(text, span, delimiters).0,
+        span,
+    }
+}
+
+/// Splits text into literal text and expressions.
+///
+/// # Returns
+///
+/// The pieces of the text, and whether it ends in an expression without its
+/// closing delimiter, which is kept as text.
+fn split_inlines(text: &str, span: Span, delimiters: &Delimiters) -> (Vec<Inline>, bool) {
+    let (open, close) = (delimiters.open.as_str(), delimiters.close.as_str());
+    let mut inlines = Vec::new();
+    let mut literal = String::new();
+    let mut unclosed = false;
+    let mut pos = 0;
+
+    while let Some(c) = text[pos..].chars().next() {
+        let rest = &text[pos..];
+        // An empty delimiter would match without moving on
+        if let Some(code) = rest.strip_prefix(open).filter(|_| !open.is_empty()) {
+            match code.find(close).filter(|_| !close.is_empty()) {
+                Some(len) => {
+                    if !literal.is_empty() {
+                        inlines.push(Inline::Text(std::mem::take(&mut literal)));
+                    }
+                    // The expression may start on a later line of the text
+                    let line_start = text[..pos].rfind('\n').map_or(0, |i| i + 1);
+                    let end = open.len() + len + close.len();
+                    inlines.push(Inline::Expr {
+                        code: code[..len].to_string(),
+                        span: Span {
+                            start: span.start + pos,
+                            end: span.start + pos + end,
+                            line: span.line + text[..pos].matches('\n').count(),
+                            // Every line starts after the prefix of the
+                            // directive around it
+                            column: text[line_start..pos].chars().count() + span.column.max(1),
+                        },
+                    });
+                    pos += end;
+                    continue;
+                }
+                None => unclosed = true,
+            }
+        }
+        if c == '`' {
+            // A code span is copied as it is, an unmatched run of backticks
+            // is only text
+            let ticks = rest.len() - rest.trim_start_matches('`').len();
+            let len = code_span_len(rest, ticks).unwrap_or(ticks);
+            literal.push_str(&rest[..len]);
+            pos += len;
+            continue;
+        }
+        if c == LAMBDA_ESCAPE {
+            let escaped = &rest[1..];
+            if !open.is_empty() && escaped.starts_with(open) {
+                literal.push_str(open);
+                pos += 1 + open.len();
+                continue;
+            }
+            if escaped.starts_with('`') {
+                literal.push_str(&rest[..2]);
+                pos += 2;
+                continue;
+            }
+        }
+        literal.push(c);
+        pos += c.len_utf8();
+    }
+    if !literal.is_empty() {
+        inlines.push(Inline::Text(literal));
+    }
+
+    (inlines, unclosed)
+}
+
+/// Finds the end of a code span.
+///
+/// # Arguments
+///
+/// * `text` - Text starting with the code span's opening backticks.
+/// * `ticks` - The number of opening backticks.
+///
+/// # Returns
+///
+/// The length of the code span including both backtick runs, or `None` if
+/// there is no run of exactly `ticks` backticks to close it.
+fn code_span_len(text: &str, ticks: usize) -> Option<usize> {
+    let mut pos = ticks;
+    while let Some(start) = text[pos..].find('`') {
+        let start = pos + start;
+        let run = text[start..].len() - text[start..].trim_start_matches('`').len();
+        if run == ticks {
+            return Some(start + run);
+        }
+        pos = start + run;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directive(block: &Block) -> &Directive {
+        match block {
+            Block::Directive(directive) => directive,
+            other => panic!("Expected a directive, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_nested_directives() {
+        let document = parse(":::info\nouter\n:::tip\ninner\n:::\n:::");
+
+        assert_eq!(document.blocks.len(), 1);
+        let info = directive(&document.blocks[0]);
+        assert_eq!(info.name, "info");
+        assert!(info.closed);
+        assert_eq!(info.span.line, 1);
+        assert_eq!(info.children.len(), 2);
+
+        let tip = directive(&info.children[1]);
+        assert_eq!(tip.name, "tip");
+        assert_eq!(tip.args, "");
+        assert_eq!(tip.open_span.line, 3);
+        assert_eq!(tip.children.len(), 1);
+    }
+
+    #[test]
+    fn test_directive_arguments() {
+        let document = parse(":::tab  First tab \n:::\n:::tab:x\n");
+
+        let tab = directive(&document.blocks[0]);
+        assert_eq!(tab.name, "tab");
+        assert_eq!(tab.args, "First tab");
+        assert!(matches!(&document.blocks[1], Block::Line(line) if line.text == ":::tab:x"));
+    }
+
+    #[test]
+    fn test_attributes() {
+        let document =
+            parse(":::tip Back up first {#backup .wide .red data-x=1 title=\"a b\" open}\n:::");
+
+        let tip = directive(&document.blocks[0]);
+        assert_eq!(tip.args, "Back up first");
+        assert_eq!(tip.attributes.id.as_deref(), Some("backup"));
+        assert_eq!(tip.attributes.classes, vec!["wide", "red"]);
+        assert_eq!(tip.attributes.get("data-x"), Some("1"));
+        assert_eq!(tip.attributes.get("title"), Some("a b"));
+        assert_eq!(tip.attributes.get("open"), Some(""));
+
+        assert_eq!(
+            split_attributes("Sets {x} and {y"),
+            ("Sets {x} and {y", Attributes::default())
+        );
+        assert_eq!(split_attributes("Use {=x}").0, "Use {=x}");
+    }
+
+    #[test]
+    fn test_unmatched_and_unclosed() {
+        let document = parse(":::\n:::tip\ntext");
+
+        assert!(matches!(document.blocks[0], Block::UnmatchedClose(_)));
+        let tip = directive(&document.blocks[1]);
+        assert!(!tip.closed);
+        assert_eq!(tip.span.end, 15);
+    }
+
+    #[test]
+    fn test_chunks_and_code_blocks() {
+        let input = "```{rhai}\nlet x = 1;\n:::\n```\n```python\n:::tip\n```";
+        let document = parse(input);
+
+        match &document.blocks[0] {
+            Block::Chunk(chunk) => {
+                assert_eq!(chunk.kind, ChunkKind::Hidden);
+                assert_eq!(chunk.code, "let x = 1;\n:::");
+                assert_eq!(chunk.code_line, 2);
+            }
+            other => panic!("Expected a chunk, found {:?}", other),
+        }
+        match &document.blocks[1] {
+            Block::CodeBlock(block) => {
+                assert_eq!(block.open.text, "```python");
+                assert_eq!(block.lines[0].text, ":::tip");
+                assert!(block.close.is_some());
+            }
+            other => panic!("Expected a code block, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_code_fences() {
+        let input = "````markdown\n```{rhai}\n:::tip\n```\n````\n~~~  \n``` not closed by backticks\n~~~~ \n```{rhai-display}   \n1\n~~~\n``` \n``` `code` ```";
+        let document = parse(input);
+
+        let code_block = |block: &Block| match block {
+            Block::CodeBlock(block) => block.clone(),
+            other => panic!("Expected a code block, found {:?}", other),
+        };
+        let markdown = code_block(&document.blocks[0]);
+        assert_eq!(markdown.lines.len(), 3);
+        assert_eq!(markdown.close.unwrap().text, "````");
+
+        let tildes = code_block(&document.blocks[1]);
+        assert_eq!(tildes.lines[0].text, "``` not closed by backticks");
+        assert_eq!(tildes.close.unwrap().text, "~~~~ ");
+
+        match &document.blocks[2] {
+            Block::Chunk(chunk) => {
+                assert_eq!(chunk.kind, ChunkKind::Display);
+                assert_eq!(chunk.code, "1\n~~~");
+                assert!(chunk.closed);
+            }
+            other => panic!("Expected a chunk, found {:?}", other),
+        }
+        assert!(matches!(&document.blocks[3], Block::Line(line) if line.text == "``` `code` ```"));
+    }
+
+    #[test]
+    fn test_directives_in_lists_and_quotes() {
+        let input = "- item\n  :::tip\n  text\n    :::note\n    nested\n    :::\n  :::\n> :::warning\n> ```{rhai}\n> let x = 1;\n> ```\n>\n> λ#(x)#\n> :::";
+        let document = parse(input);
+
+        let tip = directive(&document.blocks[1]);
+        assert_eq!(tip.prefix, "  ");
+        assert!(tip.closed);
+        assert!(matches!(&tip.children[0], Block::Line(line) if line.text == "text"));
+        let note = directive(&tip.children[1]);
+        assert_eq!(note.prefix, "  ");
+        assert!(matches!(&note.children[0], Block::Line(line) if line.text == "nested"));
+
+        let warning = directive(&document.blocks[2]);
+        assert_eq!(warning.prefix, "> ");
+        assert!(warning.closed);
+        match &warning.children[0] {
+            Block::Chunk(chunk) => {
+                assert_eq!(chunk.code, "let x = 1;");
+                assert!(chunk.closed);
+            }
+            other => panic!("Expected a chunk, found {:?}", other),
+        }
+        match &warning.children[2] {
+            Block::Line(line) => assert!(matches!(
+                &line.inlines[0],
+                Inline::Expr { span, .. } if span.line == 13 && span.column == 3
+            )),
+            other => panic!("Expected a line, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_pandoc_fenced_divs() {
+        let input = "::: {.warning #backup}\n:::: note\ntext\n::::\n::: {data-x=1} :::\n:::\n:::tip\n:::\n:::";
+        let document = parse_with_syntax(input, Syntax::Pandoc);
+
+        assert_eq!(document.blocks.len(), 1);
+        let warning = directive(&document.blocks[0]);
+        assert_eq!(warning.name, "warning");
+        assert_eq!(warning.attributes.id.as_deref(), Some("backup"));
+        assert!(warning.attributes.classes.is_empty());
+        assert!(warning.closed);
+
+        let names: Vec<_> = warning
+            .children
+            .iter()
+            .map(|block| directive(block).name.as_str())
+            .collect();
+        assert_eq!(names, vec!["note", "", "tip"]);
+        assert_eq!(
+            directive(&warning.children[1]).attributes.get("data-x"),
+            Some("1")
+        );
+
+        // Only MyST takes the kind of an admonition from its class
+        let admonition = parse_with_syntax("::: {.admonition .tip}\n:::", Syntax::Pandoc);
+        assert_eq!(directive(&admonition.blocks[0]).name, "admonition");
+        assert_eq!(
+            directive(&admonition.blocks[0]).attributes.classes,
+            vec!["tip"]
+        );
+
+        // A class that is not a directive name is not a div either
+        assert!(matches!(
+            &parse_with_syntax("::: {.\"a><b\"}\n:::", Syntax::Pandoc).blocks[0],
+            Block::Line(_)
+        ));
+
+        // Without the pandoc syntax these are ordinary text
+        assert!(matches!(&parse("::: {.warning}").blocks[0], Block::Line(_)));
+    }
+
+    #[test]
+    fn test_myst_directives() {
+        let input = "````{admonition} Read this\n:class: tip extra\n:name: first\n```{note}\n:open:\ninner\n```\n```python\nprint()\n```\n````\n:::{warning} Careful\n:::";
+        let document = parse_with_syntax(input, Syntax::Myst);
+
+        assert_eq!(document.blocks.len(), 2);
+        let tip = directive(&document.blocks[0]);
+        assert_eq!(tip.name, "tip");
+        assert_eq!(tip.args, "Read this");
+        assert_eq!(tip.attributes.classes, vec!["extra"]);
+        assert_eq!(tip.attributes.id.as_deref(), Some("first"));
+        assert!(tip.closed);
+        assert_eq!(tip.children.len(), 2);
+
+        let note = directive(&tip.children[0]);
+        assert_eq!(note.attributes.get("open"), Some(""));
+        assert!(matches!(&note.children[0], Block::Line(line) if line.text == "inner"));
+        assert!(matches!(&tip.children[1], Block::CodeBlock(_)));
+
+        let warning = directive(&document.blocks[1]);
+        assert_eq!(
+            (warning.name.as_str(), warning.args.as_str()),
+            ("warning", "Careful")
+        );
+        assert!(warning.closed);
+    }
+
+    #[test]
+    fn test_myst_colon_fences() {
+        let input = "::::{note}\n:::{tip}\ninner\n:::\nafter\n:::\n::::\n::::{warning}\n:::::";
+        let document = parse_with_syntax(input, Syntax::Myst);
+
+        assert_eq!(document.blocks.len(), 2);
+        let note = directive(&document.blocks[0]);
+        assert!(note.closed);
+        assert_eq!(note.children.len(), 3);
+        let tip = directive(&note.children[0]);
+        assert!(matches!(&tip.children[..], [Block::Line(line)] if line.text == "inner"));
+        // Three colons cannot close a directive opened with four
+        assert!(matches!(&note.children[2], Block::Line(line) if line.text == ":::"));
+
+        // Any longer line of colons closes it as well
+        assert!(directive(&document.blocks[1]).closed);
+    }
+
+    #[test]
+    fn test_code_spans_and_escapes() {
+        let line = parse_line(
+            r"`λ#(1)#` ``a ` λ#(2)#`` \λ#(3)# \`λ#(4)#` λ#(5)#",
+            Span::default(),
+            &Delimiters::default(),
+        );
+
+        assert_eq!(
+            line.inlines[0],
+            Inline::Text(r"`λ#(1)#` ``a ` λ#(2)#`` λ#(3)# \`".to_string())
+        );
+        assert!(matches!(&line.inlines[1], Inline::Expr { code, .. } if code == "4"));
+        assert!(matches!(&line.inlines[3], Inline::Expr { code, .. } if code == "5"));
+
+        let document = parse("```markdown {expand}\nλ#(1)#\n```\n```markdown\n```");
+        let expand: Vec<_> = document
+            .blocks
+            .iter()
+            .map(|block| matches!(block, Block::CodeBlock(code) if code.expand))
+            .collect();
+        assert_eq!(expand, vec![true, false]);
+    }
+
+    #[test]
+    fn test_multi_line_expressions() {
+        let input = "a λ#(\n  1 +\n  2)# b\nλ#(never closed\n\ntext {{x}}";
+        let document = parse(input);
+
+        assert_eq!(document.blocks.len(), 4);
+        match &document.blocks[0] {
+            Block::Line(line) => {
+                assert_eq!(line.text, "a λ#(\n  1 +\n  2)# b");
+                assert!(
+                    matches!(&line.inlines[1], Inline::Expr { code, span } if code == "\n  1 +\n  2" && span.line == 1 && span.column == 3)
+                );
+            }
+            other => panic!("Expected a line, found {:?}", other),
+        }
+        assert!(matches!(&document.blocks[1], Block::Line(line) if line.inlines.len() == 1));
+
+        let document = parse_with(
+            "Sum:\n{{ 1 +\n2 }} and `{{x}}`",
+            Syntax::default(),
+            &Delimiters::braces(),
+        );
+        match &document.blocks[1] {
+            Block::Line(line) => {
+                assert!(
+                    matches!(&line.inlines[0], Inline::Expr { code, span } if code == " 1 +\n2 " && span.line == 2)
+                );
+                assert_eq!(line.inlines[1], Inline::Text(" and `{{x}}`".to_string()));
+            }
+            other => panic!("Expected a line, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_inline_expressions() {
+        let line = parse_line(
+            "a λ#(1 + 1)# b λ#(x)#",
+            Span::default(),
+            &Delimiters::default(),
+        );
+
+        assert_eq!(
+            line.inlines,
+            vec![
+                Inline::Text("a ".to_string()),
+                Inline::Expr {
+                    code: "1 + 1".to_string(),
+                    span: Span {
+                        start: 2,
+                        end: 13,
+                        line: 0,
+                        column: 3
+                    }
+                },
+                Inline::Text(" b ".to_string()),
+                Inline::Expr {
+                    code: "x".to_string(),
+                    span: Span {
+                        start: 16,
+                        end: 23,
+                        line: 0,
+                        column: 16
+                    }
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_empty_delimiters() {
+        assert!(Delimiters::new("", "").is_err());
+        assert!(Delimiters::new("{{", "").is_err());
+        assert_eq!(Delimiters::new("{{", "}}"), Ok(Delimiters::braces()));
+
+        // Empty delimiters never match, rather than matching forever
+        for (open, close) in [("", ""), ("{{", "")] {
+            let delimiters = Delimiters {
+                open: open.to_string(),
+                close: close.to_string(),
+            };
+            let line = parse_line("a {{1}} b", Span::default(), &delimiters);
+            assert_eq!(line.inlines, vec![Inline::Text("a {{1}} b".to_string())]);
+        }
+    }
+}
@@ -1,87 +1,191 @@
 //! This module provides functionality for processing markdown-like text
 //! with custom admonitions and code blocks.
-
-use regex::Regex;
-use rhai::packages::{BasicMathPackage, CorePackage, Package};
+//!
+//! The input is first parsed into a tree (see [`crate::parser`]) which is
+//! then rendered back to markdown with the custom syntax expanded.
+
+use crate::admonition::AdmonitionRegistry;
+use crate::diagnostic::Diagnostic;
+use crate::extension::DraftsmithExtension;
+use crate::parser::{
+    self, Attributes, Block, Chunk, ChunkKind, CodeBlock, Delimiters, Directive, Inline, Line,
+    Span, Syntax, ADMONITION_ALIASES,
+};
+use crate::script::{Budget, EngineSettings, Prelude, ScriptLimits, ScriptPackages};
+use crate::style::{add_attributes, Breakpoint, ColumnWidth, StyleBackend, TabHeader};
 use rhai::{Engine, Scope};
-
-pub type CustomFn = Box<dyn Fn(&mut Engine)>;
-
-const ADMONITION_START_PATTERN: &str = r"^\s*:::([\w!\{\}-]+)$";
-const ADMONITION_END_PATTERN: &str = r"^\s*(:::)$";
-const CODE_START_PATTERN: &str = r"^\s*```\{rhai\}$";
-const RHAI_DISPLAY_START_PATTERN: &str = r"^\s*```\{rhai-display\}$";
-const CODE_END_PATTERN: &str = r"^\s*```$";
-const LAMBDA_PATTERN: &str = r"λ#\(((?s).*?)\)#";
-const TABS_START_PATTERN: &str = r"^\s*:::tabs$";
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// The summary of a `:::fold` block without a title or `:::summary`.
+const DEFAULT_FOLD_SUMMARY: &str = "📂";
+/// The number of cards per row in a `:::cards` grid without one.
+const DEFAULT_CARD_COLUMNS: usize = 3;
+/// The attributes of a `:::card` block that are not added to its element.
+const CARD_ATTRIBUTES: [&str; 3] = ["title", "img", "alt"];
+
+/// A closure that registers functions with the Rhai engine directly.
+///
+/// Prefer a [`DraftsmithExtension`], which can keep state in its fields and
+/// lists what it registers.
+pub type CustomFn = Box<dyn Fn(&mut Engine) + Send + Sync>;
+
+/// The result of processing a document.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Rendered {
+    /// The rendered document.
+    pub output: String,
+    /// Problems found while rendering, in document order.
+    pub diagnostics: Vec<Diagnostic>,
+}
 
 /// A processor for handling custom markdown-like syntax.
 pub struct Processor<'a> {
-    admonition_start_regex: Regex,
-    admonition_end_regex: Regex,
-    code_start_regex: Regex,
-    rhai_display_start_regex: Regex,
-    code_end_regex: Regex,
-    lambda_regex: Regex,
-    tabs_start_regex: Regex,
-    div_stack: Vec<String>,
-    eval_stack: bool,
-    is_rhai_display: bool,
-    contents: Vec<String>,
-    rhai_engine: Engine,
+    rhai_engine: Arc<Engine>,
     rhai_scope: Scope<'a>,
-    in_tabs: bool,
-    tab_count: usize,
-    tabs_closing: bool, // Add this new field
-    current_indent: String,
+    admonitions: Arc<AdmonitionRegistry>,
+    style: StyleBackend,
+    syntax: Syntax,
+    delimiters: Delimiters,
+    limits: ScriptLimits,
+    /// What the engine was built from, `None` for a shared engine.
+    engine_settings: Option<EngineSettings>,
+    /// When the scripts of the current document run out of time.
+    deadline: Option<Instant>,
+    tab_groups: usize,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl<'a> Processor<'a> {
     /// Creates a new Processor with optional Rhai function registrations
     pub fn new(functions: Option<Vec<CustomFn>>) -> Self {
-        let mut processor = Self::default();
+        let settings = EngineSettings::new(functions);
+        let mut processor = Self::with_engine(Arc::new(settings.build()));
+        processor.engine_settings = Some(settings);
+        processor
+    }
 
-        // Register any provided functions with the Rhai engine
-        if let Some(fns) = functions {
-            for register_fn in fns {
-                register_fn(&mut processor.rhai_engine);
-            }
+    /// Creates a new Processor that evaluates code with a shared engine.
+    ///
+    /// The processor still has its own scope, so variables are not shared
+    /// with other processors using the same engine.
+    pub fn with_engine(engine: Arc<Engine>) -> Self {
+        Self {
+            rhai_engine: engine,
+            rhai_scope: Scope::new(),
+            admonitions: Arc::new(AdmonitionRegistry::default()),
+            style: StyleBackend::default(),
+            syntax: Syntax::default(),
+            delimiters: Delimiters::default(),
+            limits: ScriptLimits::default(),
+            engine_settings: None,
+            deadline: None,
+            tab_groups: 0,
+            diagnostics: Vec::new(),
         }
+    }
 
-        processor
+    /// Produces markup for `style`.
+    ///
+    /// This also replaces the admonitions with the built-in ones of `style`,
+    /// so call [`Processor::with_admonitions`] afterwards to customise them.
+    pub fn with_style(mut self, style: StyleBackend) -> Self {
+        self.style = style;
+        self.admonitions = Arc::new(style.admonitions());
+        self
+    }
+
+    /// Uses `admonitions` for the markup of `:::name` blocks, either a
+    /// registry or one already shared behind an [`Arc`].
+    pub fn with_admonitions(mut self, admonitions: impl Into<Arc<AdmonitionRegistry>>) -> Self {
+        self.admonitions = admonitions.into();
+        self
+    }
+
+    /// Also accepts the directives of `syntax`, e.g. pandoc fenced divs.
+    pub fn with_syntax(mut self, syntax: Syntax) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    /// Uses `delimiters` around inline expressions instead of `λ#(` and `)#`.
+    pub fn with_delimiters(mut self, delimiters: Delimiters) -> Self {
+        self.delimiters = delimiters;
+        self
+    }
+
+    /// Evaluates scripts within `limits` instead of the default ones.
+    ///
+    /// The operation and time limits apply to any engine. The size and depth
+    /// limits are settings of the engine, so a processor sharing one through
+    /// [`Processor::with_engine`] keeps those of its engine.
+    pub fn with_limits(mut self, limits: ScriptLimits) -> Self {
+        self.limits = limits;
+        if let Some(settings) = &mut self.engine_settings {
+            settings.limits = limits;
+            self.rhai_engine = Arc::new(settings.build());
+        }
+        self
+    }
+
+    /// Gives scripts the functions of `packages` instead of the core and
+    /// math ones.
+    ///
+    /// The packages are part of the engine, so this has no effect on a
+    /// processor sharing one through [`Processor::with_engine`].
+    pub fn with_packages(mut self, packages: ScriptPackages) -> Self {
+        if let Some(settings) = &mut self.engine_settings {
+            settings.packages = packages;
+            self.rhai_engine = Arc::new(settings.build());
+        }
+        self
+    }
+
+    /// Resolves `import "helpers/finance" as fin;` to `.rhai` files in
+    /// `directory`, see [`Renderer::with_modules`](crate::renderer::Renderer::with_modules).
+    ///
+    /// The module resolver is part of the engine, so this has no effect on a
+    /// processor sharing one through [`Processor::with_engine`].
+    pub fn with_modules(mut self, directory: impl Into<PathBuf>) -> Self {
+        if let Some(settings) = &mut self.engine_settings {
+            settings.modules = Some(directory.into());
+            self.rhai_engine = Arc::new(settings.build());
+        }
+        self
+    }
+
+    /// Makes the functions and constants of `prelude` available to every
+    /// chunk and inline expression. Call it again for more preludes.
+    ///
+    /// Preludes are part of the engine, so this has no effect on a
+    /// processor sharing one through [`Processor::with_engine`].
+    pub fn with_prelude(mut self, prelude: Prelude) -> Self {
+        if let Some(settings) = &mut self.engine_settings {
+            settings.preludes.push(prelude);
+            self.rhai_engine = Arc::new(settings.build());
+        }
+        self
+    }
+
+    /// Registers the functions, types and constants of `extension`. Call it
+    /// again for more extensions.
+    ///
+    /// Extensions are part of the engine, so this has no effect on a
+    /// processor sharing one through [`Processor::with_engine`].
+    pub fn with_extension(mut self, extension: impl DraftsmithExtension + 'static) -> Self {
+        if let Some(settings) = &mut self.engine_settings {
+            settings.extensions.push(Box::new(extension));
+            self.rhai_engine = Arc::new(settings.build());
+        }
+        self
     }
 }
 
 impl<'a> Default for Processor<'a> {
     fn default() -> Self {
-        Self {
-            admonition_start_regex: Regex::new(ADMONITION_START_PATTERN)
-                .expect("Failed to compile regex"),
-            admonition_end_regex: Regex::new(ADMONITION_END_PATTERN)
-                .expect("Failed to compile regex"),
-            code_start_regex: Regex::new(CODE_START_PATTERN).expect("Failed to compile regex"),
-            rhai_display_start_regex: Regex::new(RHAI_DISPLAY_START_PATTERN)
-                .expect("Failed to compile regex"),
-            code_end_regex: Regex::new(CODE_END_PATTERN).expect("Failed to compile regex"),
-            lambda_regex: Regex::new(LAMBDA_PATTERN).expect("Failed to compile regex"),
-            tabs_start_regex: Regex::new(TABS_START_PATTERN).expect("Failed to compile regex"),
-            div_stack: Vec::new(),
-            eval_stack: false,
-            is_rhai_display: false,
-            contents: Vec::new(),
-            rhai_engine: {
-                let mut engine = Engine::new_raw();
-                // Register the package into the 'Engine'.
-                CorePackage::new().register_into_engine(&mut engine);
-                BasicMathPackage::new().register_into_engine(&mut engine);
-                engine
-            },
-            rhai_scope: Scope::new(),
-            in_tabs: false,
-            tab_count: 0,
-            tabs_closing: false,
-            current_indent: String::new(),
-        }
+        Self::new(None)
     }
 }
 
@@ -94,15 +198,18 @@ impl<'a> Processor<'a> {
     ///
     /// # Returns
     ///
-    /// A `String` containing the processed text with custom syntax transformed.
-    pub fn process(&mut self, input: &str) -> String {
-        input
-            .lines()
-            .map(|line| self.process_line(line))
-            .collect::<Vec<String>>()
-            .join("")
-            .trim_end_matches('\n')
-            .to_string()
+    /// A [`Rendered`] containing the processed text with custom syntax
+    /// transformed, and any problems found along the way.
+    pub fn process(&mut self, input: &str) -> Rendered {
+        let document = parser::parse_with(input, self.syntax, &self.delimiters);
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        self.tab_groups = 0;
+        let mut output = String::new();
+        self.render_blocks(&document.blocks, &mut output);
+        Rendered {
+            output: output.trim_end_matches('\n').to_string(),
+            diagnostics: std::mem::take(&mut self.diagnostics),
+        }
     }
 
     /// Evaluates Rhai code and returns a formatted string of the results.
@@ -111,243 +218,640 @@ impl<'a> Processor<'a> {
     ///
     /// * `engine` - A reference to the Rhai Engine.
     /// * `scope` - A mutable reference to the Rhai Scope.
+    /// * `budget` - The operations and time the code may use.
     /// * `captured` - A string slice containing the Rhai code to evaluate.
     ///
     /// # Returns
     ///
-    /// A `String` containing the formatted evaluation results.
-    fn process_lambda(engine: &Engine, scope: &mut Scope, captured: &str) -> String {
-        match engine.eval_with_scope::<rhai::Dynamic>(scope, captured) {
-            Ok(result) => format!("{}", result),
-            Err(err) => format!("Error: {}", err),
+    /// A `String` containing the formatted evaluation results, or the error
+    /// raised by the script.
+    fn process_lambda(
+        engine: &Engine,
+        scope: &mut Scope,
+        budget: Budget,
+        captured: &str,
+    ) -> Result<String, Box<rhai::EvalAltResult>> {
+        budget
+            .run(|| engine.eval_with_scope::<rhai::Dynamic>(scope, captured))
+            .map(|result| format!("{}", result))
+    }
+
+    /// The budget of the next chunk or expression.
+    fn budget(&self) -> Budget {
+        Budget {
+            max_operations: self.limits.max_operations,
+            deadline: self.deadline,
+            timeout: self.limits.timeout,
         }
     }
 
-    /// Processes a single line of text.
+    /// Renders a sequence of blocks in document order.
     ///
     /// # Arguments
     ///
-    /// * `line` - A string slice that holds the line to be processed.
+    /// * `blocks` - The blocks to render.
+    /// * `output` - The string the rendered markdown is appended to.
+    fn render_blocks(&mut self, blocks: &[Block], output: &mut String) {
+        for block in blocks {
+            self.render_block(block, output);
+        }
+    }
+
+    fn render_block(&mut self, block: &Block, output: &mut String) {
+        match block {
+            Block::Line(line) => output.push_str(&self.render_line(line)),
+            // Directives and chunks inside list items and block quotes keep
+            // their indentation or `>`, so that they stay inside them
+            Block::Directive(directive) if !directive.prefix.is_empty() => {
+                let mut rendered = String::new();
+                self.render_directive(directive, &mut rendered);
+                output.push_str(&prefix_lines(&rendered, &directive.prefix));
+            }
+            Block::Directive(directive) => self.render_directive(directive, output),
+            Block::Chunk(chunk) => {
+                let rendered = self.render_chunk(chunk);
+                output.push_str(&prefix_lines(&rendered, &chunk.prefix));
+            }
+            Block::CodeBlock(code) => self.render_code_block(code, output),
+            Block::UnmatchedClose(span) => {
+                self.diagnostics.push(Diagnostic::warning(
+                    "`:::` does not close any block",
+                    ":::",
+                    *span,
+                ));
+                output.push_str(":::\n");
+            }
+        }
+    }
+
+    /// Renders a directive with the markup for its name.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// A `String` containing the processed line.
-    fn process_line(&mut self, line: &str) -> String {
-        if self.tabs_closing && self.admonition_end_regex.is_match(line) {
-            self.tabs_closing = false;
-            return String::new(); // Ignore the final ":::" when closing a tabs block
-        }
-
-        if self.tabs_start_regex.is_match(line) {
-            self.handle_tabs_start()
-        } else if self.code_start_regex.is_match(line) {
-            self.handle_code_start(false)
-        } else if self.rhai_display_start_regex.is_match(line) {
-            self.handle_code_start(true)
-        } else if self.code_end_regex.is_match(line) {
-            self.handle_code_end()
-        } else if self.admonition_start_regex.is_match(line) {
-            if let Some(caps) = self.admonition_start_regex.captures(line) {
-                if self.in_tabs && &caps[1] == "tab" {
-                    self.handle_tab()
-                } else {
-                    self.handle_admonition_start(&caps[1])
-                }
-            } else {
-                String::new()
+    /// * `directive` - The parsed `:::name` block.
+    /// * `output` - The string the rendered markdown is appended to.
+    fn render_directive(&mut self, directive: &Directive, output: &mut String) {
+        match directive.name.as_str() {
+            "tabs" => self.render_tabs(directive, output),
+            "fold" => self.render_fold(directive, output),
+            "card" => self.render_card(directive, output),
+            "cards" => self.render_cards(directive, output),
+            "columns" => self.render_columns(directive, output),
+            "" => self.render_div(directive, output),
+            "tab" | "column" => {
+                // Tabs and columns only mean something inside their group
+                self.diagnostics.push(Diagnostic::warning(
+                    format!("`:::{0}` is not inside a `:::{0}s` block", directive.name),
+                    format!(":::{}", directive.name),
+                    directive.open_span,
+                ));
+                self.render_admonition(directive, output)
             }
-        } else if self.admonition_end_regex.is_match(line) {
-            if self.in_tabs {
-                self.handle_tab_end()
-            } else {
-                self.handle_admonition_end()
-            }
-        } else {
-            self.handle_regular_line(line)
+            _ => self.render_admonition(directive, output),
         }
     }
 
-    /// Handles the start of an admonition block.
+    /// Renders an admonition block and its contents using the markup
+    /// registered for its name.
     ///
     /// # Arguments
     ///
-    /// * `class` - The class of the admonition.
+    /// * `directive` - The parsed `:::class` block.
+    /// * `output` - The string the rendered markdown is appended to.
+    fn render_admonition(&mut self, directive: &Directive, output: &mut String) {
+        self.check_closed(directive);
+        let name = self.admonition_name(&directive.name);
+        let (open, close) = self
+            .admonitions
+            .render(name, &directive.args, &directive.attributes);
+
+        output.push_str(&open);
+        output.push('\n');
+        self.render_blocks(&directive.children, output);
+        output.push_str(&close);
+        output.push('\n');
+    }
+
+    /// Renders a pandoc div without a class as a plain `<div>` that only
+    /// carries the attributes written on its opening line.
     ///
-    /// # Returns
+    /// # Arguments
+    ///
+    /// * `directive` - The parsed `::: {#id}` block.
+    /// * `output` - The string the rendered markdown is appended to.
+    fn render_div(&mut self, directive: &Directive, output: &mut String) {
+        self.check_closed(directive);
+        output.push_str(&add_attributes("<div>", &directive.attributes));
+        output.push('\n');
+        self.render_blocks(&directive.children, output);
+        output.push_str("</div>\n");
+    }
+
+    /// The registered admonition a block name is rendered with. In pandoc and
+    /// MyST documents, a kind such as `note` that has no admonition of its
+    /// own is rendered as its built-in alias from [`ADMONITION_ALIASES`].
+    fn admonition_name<'n>(&self, name: &'n str) -> &'n str {
+        if self.syntax == Syntax::Draftsmith || self.admonitions.get(name).is_some() {
+            return name;
+        }
+        ADMONITION_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == name)
+            .map_or(name, |(_, target)| target)
+    }
+
+    /// Renders a `:::fold` block as a single `<details>` element.
+    ///
+    /// The summary is the contents of a `:::summary` child if there is one,
+    /// otherwise the title on the opening line or 📂. `:::fold Title {open}`
+    /// starts open.
+    ///
+    /// # Arguments
     ///
-    /// A `String` containing the opening HTML div tag for the admonition.
-    fn handle_admonition_start(&mut self, class: &str) -> String {
-        if class.is_empty() {
-            return String::new(); // Return an empty string for empty admonitions
-        }
-        let html = match class {
-            "alert" => "<div role=\"alert\" class=\"alert alert-info\">".to_string(),
-            "info" => "<div class=\"admonition note\">".to_string(),
-            "success" => "<div class=\"alert alert-success\">".to_string(),
-            "warning" => "<div class=\"admonition important\">".to_string(),
-            "error" => "<div class=\"admonition warning\">".to_string(),
-            "tip" => "<div class=\"admonition tip\">".to_string(),
-            "fold" => "<details class=\"my-details\"><summary>📂</summary>".to_string(),
-            "summary" => "<summary class=\"my-summary\">".to_string(),
-            "col" => "<div class=\"flex w-full flex-col lg:flex-row\">".to_string(),
-            "card" => "<div class=\"card bg-base-100 w-96 shadow-xl\">".to_string(),
-            _ => format!("<div class=\"{}\">", class),
+    /// * `directive` - The parsed `:::fold` block.
+    /// * `output` - The string the rendered markdown is appended to.
+    fn render_fold(&mut self, directive: &Directive, output: &mut String) {
+        self.check_closed(directive);
+        let summary = directive
+            .children
+            .iter()
+            .position(|block| matches!(block, Block::Directive(child) if child.name == "summary"));
+        let title = match (summary, directive.args.as_str()) {
+            (Some(_), _) => "",
+            (None, "") => DEFAULT_FOLD_SUMMARY,
+            (None, title) => title,
         };
-        self.div_stack.push(class.to_string());
-        format!("{}\n", html)
+        let (open, close) = self
+            .admonitions
+            .render(&directive.name, title, &directive.attributes);
+
+        output.push_str(&open);
+        // The summary goes straight after the opening tag, wherever it is
+        // written in the block
+        match summary.map(|index| &directive.children[index]) {
+            Some(Block::Directive(child)) => self.render_admonition(child, output),
+            _ => output.push('\n'),
+        }
+        for (index, block) in directive.children.iter().enumerate() {
+            if Some(index) != summary {
+                self.render_block(block, output);
+            }
+        }
+        output.push_str(&close);
+        output.push('\n');
     }
 
-    /// Handles the end of an admonition block.
+    /// Renders a `:::tabs` block.
     ///
-    /// # Returns
+    /// Each `:::tab Title` child becomes a tab panel in the markup of the
+    /// style backend, untitled tabs are labelled "Tab 1", "Tab 2" and so on.
+    /// `:::tabs 2` shows the second tab first. Anything after the last tab is
+    /// placed after the tab list.
     ///
-    /// A `String` containing the closing HTML tag for the admonition.
-    fn handle_admonition_end(&mut self) -> String {
-        if self.in_tabs {
-            self.handle_tab_end()
-        } else {
-            self.div_stack.pop().map_or_else(
-                || String::from(":::\n"),
-                |class| match class.as_str() {
-                    "fold" => "</details>\n".to_string(),
-                    "summary" => "</summary>\n".to_string(),
-                    _ => "</div>\n".to_string(),
+    /// # Arguments
+    ///
+    /// * `directive` - The parsed `:::tabs` block.
+    /// * `output` - The string the rendered markdown is appended to.
+    fn render_tabs(&mut self, directive: &Directive, output: &mut String) {
+        self.check_closed(directive);
+        let is_tab = |block: &Block| matches!(block, Block::Directive(d) if d.name == "tab");
+        let split = directive
+            .children
+            .iter()
+            .rposition(is_tab)
+            .map_or(0, |last| last + 1);
+        let (tabs, trailing) = directive.children.split_at(split);
+
+        let titled: Vec<&Directive> = tabs
+            .iter()
+            .filter_map(|block| match block {
+                Block::Directive(tab) if tab.name == "tab" => Some(tab),
+                _ => None,
+            })
+            .collect();
+        let default_tab = self.default_tab(directive, titled.len());
+        let headers: Vec<TabHeader> = titled
+            .iter()
+            .enumerate()
+            .map(|(index, tab)| TabHeader {
+                label: match tab.args.as_str() {
+                    "" => format!("Tab {}", index + 1),
+                    title => title.to_string(),
                 },
-            )
+                checked: index + 1 == default_tab,
+                attributes: tab.attributes.clone(),
+            })
+            .collect();
+
+        // Every group on the page needs its own name, or their radio
+        // buttons would interfere with each other
+        self.tab_groups += 1;
+        let group = format!("tabs-{}", self.tab_groups);
+
+        output.push_str(&add_attributes(
+            &self.style.tabs_open(&group, &headers),
+            &directive.attributes,
+        ));
+        let mut headers = headers.iter().enumerate();
+        for block in tabs {
+            match block {
+                Block::Directive(tab) if tab.name == "tab" => {
+                    self.check_closed(tab);
+                    if let Some((index, header)) = headers.next() {
+                        output.push_str(&self.style.tab_open(&group, index + 1, header));
+                    }
+                    self.render_blocks(&tab.children, output);
+                    output.push_str(self.style.tab_close());
+                }
+                _ => self.render_block(block, output),
+            }
         }
+        output.push_str(self.style.tabs_close());
+        self.render_blocks(trailing, output);
     }
 
-    fn handle_tabs_start(&mut self) -> String {
-        self.in_tabs = true;
-        self.tab_count = 0;
-        self.tabs_closing = false; // Reset this flag when starting a new tabs block
-        "<div role=\"tablist\" class=\"tabs tabs-lifted\">\n".to_string()
+    /// Reads the default tab from a `:::tabs N` line.
+    ///
+    /// # Arguments
+    ///
+    /// * `directive` - The parsed `:::tabs` block.
+    /// * `tab_count` - The number of tabs in the group.
+    ///
+    /// # Returns
+    ///
+    /// The 1-based number of the tab shown first, the first tab unless the
+    /// group names a valid one.
+    fn default_tab(&mut self, directive: &Directive, tab_count: usize) -> usize {
+        if directive.args.is_empty() {
+            return 1;
+        }
+        match directive.args.parse::<usize>() {
+            Ok(tab) if (1..=tab_count).contains(&tab) => tab,
+            _ => {
+                self.diagnostics.push(Diagnostic::warning(
+                    format!(
+                        "`{}` is not a tab number between 1 and {}",
+                        directive.args, tab_count
+                    ),
+                    ":::tabs",
+                    directive.open_span,
+                ));
+                1
+            }
+        }
     }
 
-    fn handle_tab(&mut self) -> String {
-        self.tab_count += 1;
-        let checked = if self.tab_count == 2 {
-            " checked=\"checked\""
-        } else {
-            ""
+    /// Renders a `:::columns` block.
+    ///
+    /// Each `:::column` child becomes a column, `:::column 1/3` or
+    /// `:::column 40%` sets its width and columns without a width share the
+    /// space left. `:::columns md` stacks the columns on screens narrower than
+    /// the `md` breakpoint instead of the default `lg`.
+    ///
+    /// # Arguments
+    ///
+    /// * `directive` - The parsed `:::columns` block.
+    /// * `output` - The string the rendered markdown is appended to.
+    fn render_columns(&mut self, directive: &Directive, output: &mut String) {
+        self.check_closed(directive);
+        let breakpoint = self.parse_args::<Breakpoint>(directive).unwrap_or_default();
+
+        output.push_str(&add_attributes(
+            &self.style.columns_open(breakpoint),
+            &directive.attributes,
+        ));
+        for block in &directive.children {
+            match block {
+                Block::Directive(column) if column.name == "column" => {
+                    self.check_closed(column);
+                    let width = self.parse_args::<ColumnWidth>(column);
+                    output.push_str(&add_attributes(
+                        &self.style.column_open(breakpoint, width),
+                        &column.attributes,
+                    ));
+                    self.render_blocks(&column.children, output);
+                    output.push_str(self.style.column_close());
+                }
+                _ => self.render_block(block, output),
+            }
+        }
+        output.push_str(self.style.columns_close());
+    }
+
+    /// Renders a `:::card` block.
+    ///
+    /// The title is the text on the opening line or a `title` attribute. The
+    /// image is an `img` attribute (with `alt`), or else the first line of
+    /// the block holding only an image. An `:::actions` or `:::footer` child
+    /// becomes the card's actions, and everything else its body.
+    ///
+    /// # Arguments
+    ///
+    /// * `directive` - The parsed `:::card` block.
+    /// * `output` - The string the rendered markdown is appended to.
+    fn render_card(&mut self, directive: &Directive, output: &mut String) {
+        self.check_closed(directive);
+        let attributes = &directive.attributes;
+        let title = match directive.args.as_str() {
+            "" => attributes.get("title").unwrap_or_default(),
+            title => title,
         };
-        format!(
-            "  <input type=\"radio\" name=\"my_tabs_2\" role=\"tab\" class=\"tab\" aria-label=\"Tab {}\"{}/>
-  <div role=\"tabpanel\" class=\"tab-content bg-base-100 border-base-300 rounded-box p-6\">\n",
-            self.tab_count, checked
-        )
-    }
-
-    fn handle_tab_end(&mut self) -> String {
-        if self.tab_count == 3 {
-            self.in_tabs = false;
-            self.tab_count = 0;
-            self.tabs_closing = true; // Set this flag when closing the tabs block
-            "  </div>\n</div>\n".to_string()
-        } else {
-            "  </div>\n".to_string()
+        let mut image = attributes.get("img").map(|src| {
+            (
+                src.to_string(),
+                attributes.get("alt").unwrap_or_default().to_string(),
+            )
+        });
+
+        let mut body = String::new();
+        let mut actions = None;
+        for block in &directive.children {
+            match block {
+                Block::Directive(child)
+                    if actions.is_none() && matches!(child.name.as_str(), "actions" | "footer") =>
+                {
+                    self.check_closed(child);
+                    let mut markup = String::new();
+                    self.render_blocks(&child.children, &mut markup);
+                    actions = Some(markup);
+                }
+                Block::Line(line) if image.is_none() && parse_image(&line.text).is_some() => {
+                    image = parse_image(&line.text);
+                }
+                _ => self.render_block(block, &mut body),
+            }
         }
+
+        let element = Attributes {
+            pairs: attributes
+                .pairs
+                .iter()
+                .filter(|(key, _)| !CARD_ATTRIBUTES.contains(&key.as_str()))
+                .cloned()
+                .collect(),
+            ..attributes.clone()
+        };
+        let (open, close) = self.admonitions.render(&directive.name, "", &element);
+        output.push_str(&open);
+        output.push('\n');
+        output.push_str(
+            &self.style.card(
+                image
+                    .as_ref()
+                    .map(|(src, alt)| (src.as_str(), alt.as_str())),
+                title,
+                &body,
+                actions.as_deref(),
+            ),
+        );
+        output.push_str(&close);
+        output.push('\n');
     }
 
-    /// Handles the start of a code block.
+    /// Renders a `:::cards` grid, `:::cards 4` puts four cards in a row on
+    /// wide screens.
     ///
-    /// # Returns
+    /// # Arguments
     ///
-    /// An empty `String` as the code block start is not directly output.
-    fn handle_code_start(&mut self, is_display: bool) -> String {
-        self.eval_stack = true;
-        self.is_rhai_display = is_display;
-        self.contents.clear();
-        // Store the indentation by counting leading spaces
-        if let Some(last_line) = self.contents.last() {
-            self.current_indent = " ".repeat(last_line.chars().take_while(|c| c.is_whitespace()).count());
-        } else {
-            // If we're at a new line, count the spaces from the current line
-            self.current_indent = " ".repeat(self.contents.len());
+    /// * `directive` - The parsed `:::cards` block.
+    /// * `output` - The string the rendered markdown is appended to.
+    fn render_cards(&mut self, directive: &Directive, output: &mut String) {
+        self.check_closed(directive);
+        let columns = match self.parse_args::<usize>(directive) {
+            Some(columns) if (1..=6).contains(&columns) => columns,
+            Some(columns) => {
+                self.diagnostics.push(Diagnostic::warning(
+                    format!("`{}` is not a number of cards between 1 and 6", columns),
+                    ":::cards",
+                    directive.open_span,
+                ));
+                DEFAULT_CARD_COLUMNS
+            }
+            None => DEFAULT_CARD_COLUMNS,
+        };
+
+        output.push_str(&add_attributes(
+            &self.style.cards_open(columns),
+            &directive.attributes,
+        ));
+        let (item_open, item_close) = self.style.cards_item();
+        for block in &directive.children {
+            match block {
+                Block::Directive(_) => {
+                    output.push_str(item_open);
+                    self.render_block(block, output);
+                    output.push_str(item_close);
+                }
+                _ => self.render_block(block, output),
+            }
         }
-        String::new()
+        output.push_str(self.style.cards_close());
     }
 
-    /// Handles the end of a code block.
+    /// Parses the text after a directive's name, recording a warning if it
+    /// is invalid.
     ///
     /// # Returns
     ///
-    /// A `String` containing the evaluated code output wrapped in HTML.
-    fn handle_code_end(&mut self) -> String {
-        if self.eval_stack {
-            self.eval_stack = false;
-            if !self.contents.is_empty() {
-                let code = self.contents.join("\n");
-                let results = Self::process_lambda(&self.rhai_engine, &mut self.rhai_scope, &code);
-                self.contents.clear();
-
-                if self.is_rhai_display {
-                    if results.trim().is_empty() {
-                        String::new()
-                    } else {
-                        format!(
-                        "<div class=\"rhai-display\">\n\n```rust\n{}\n```\n<div class=\"rhai-out\">\n\n```\n{}\n```\n</div>\n</div>\n",
-                        code, results
-                    )
-                    }
-                } else {
-                    String::new()
-                }
-            } else {
-                String::new()
+    /// The parsed value, or `None` if there is no text or it is invalid.
+    fn parse_args<T>(&mut self, directive: &Directive) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: ToString,
+    {
+        if directive.args.is_empty() {
+            return None;
+        }
+        match directive.args.parse() {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.diagnostics.push(Diagnostic::warning(
+                    error.to_string(),
+                    format!(":::{}", directive.name),
+                    directive.open_span,
+                ));
+                None
             }
-        } else {
-            format!("{}{}\n", self.current_indent, "```")
         }
     }
 
-    /// Handles a regular line of text.
+    /// Records a warning if a directive runs to the end of the document.
+    fn check_closed(&mut self, directive: &Directive) {
+        if !directive.closed {
+            self.diagnostics.push(Diagnostic::warning(
+                format!("`:::{}` is never closed", directive.name),
+                format!(":::{}", directive.name),
+                directive.open_span,
+            ));
+        }
+    }
+
+    /// Evaluates a Rhai chunk.
     ///
     /// # Arguments
     ///
-    /// * `line` - A string slice that holds the line to be processed.
+    /// * `chunk` - The parsed chunk.
     ///
     /// # Returns
     ///
-    /// A `String` containing the processed line.
-    fn handle_regular_line(&mut self, line: &str) -> String {
-        if self.eval_stack {
-            self.contents.push(line.to_string());
-            String::new()
-        } else {
-            // Count leading spaces if this is the first line after a code block
-            if line.trim_start().starts_with("```") {
-                self.current_indent = " ".repeat(line.chars().take_while(|c| c.is_whitespace()).count());
-            }
-            let mut result = String::new();
-            let mut last_end = 0;
-            let mut scope = self.rhai_scope.clone();
-            let engine = &self.rhai_engine;
-
-            for cap in self.lambda_regex.captures_iter(line) {
-                let whole_match = cap.get(0).unwrap();
-                let captured = &cap[1];
-                result.push_str(&line[last_end..whole_match.start()]);
-                result.push_str(&Self::process_lambda(engine, &mut scope, captured));
-                last_end = whole_match.end();
+    /// A `String` containing the evaluated code output wrapped in HTML for
+    /// `{rhai-display}` chunks, or an empty string for hidden chunks. A
+    /// failing `{rhai-display}` chunk shows its code without any output.
+    fn render_chunk(&mut self, chunk: &Chunk) -> String {
+        let fence = match chunk.kind {
+            ChunkKind::Hidden => "```{rhai}",
+            ChunkKind::Display => "```{rhai-display}",
+        };
+        if !chunk.closed {
+            self.diagnostics.push(Diagnostic::warning(
+                format!("`{}` is never closed", fence),
+                fence,
+                chunk.span,
+            ));
+        }
+        if chunk.code.is_empty() {
+            return String::new();
+        }
+
+        // Every line of code starts after the chunk's indentation or `>`
+        let column = chunk.span.column.max(1) + chunk.prefix.chars().count();
+        let budget = self.budget();
+        let results =
+            Self::process_lambda(&self.rhai_engine, &mut self.rhai_scope, budget, &chunk.code)
+                .map_err(|err| {
+                    self.diagnostics.push(Diagnostic::from_script_error(
+                        *err,
+                        fence,
+                        chunk.span,
+                        chunk.code_line,
+                        column,
+                        column,
+                    ))
+                });
+
+        match results {
+            _ if chunk.kind == ChunkKind::Hidden => String::new(),
+            Ok(results) if results.trim().is_empty() => String::new(),
+            Ok(results) => self.style.rhai_display(&chunk.code, Some(&results)),
+            Err(()) => self.style.rhai_display(&chunk.code, None),
+        }
+    }
+
+    /// Renders an ordinary code block, keeping its fences.
+    fn render_code_block(&mut self, code: &CodeBlock, output: &mut String) {
+        if !code.expand {
+            output.push_str(&code.open.text);
+            output.push('\n');
+            for line in code.lines.iter().chain(&code.close) {
+                output.push_str(&line.text);
+                output.push('\n');
             }
-            result.push_str(&line[last_end..]);
+            return;
+        }
 
-            // Update the main scope with any changes from the cloned scope
-            self.rhai_scope = scope;
+        // The attribute is only meant for the processor
+        let open = code.open.text.trim_end();
+        let open = open.strip_suffix(parser::EXPAND_ATTRIBUTE).unwrap_or(open);
+        output.push_str(open.trim_end());
+        output.push('\n');
+        for line in code.lines.iter().chain(&code.close) {
+            output.push_str(&self.render_line(line));
+        }
+    }
 
-            if result.trim().is_empty() {
-                "\n".to_string() // Always return a newline for empty lines
-            } else {
-                format!("{}\n", result) // Add a newline after each non-empty line
+    /// Renders a regular line of text, evaluating any inline expressions.
+    ///
+    /// # Arguments
+    ///
+    /// * `line` - The parsed line.
+    ///
+    /// # Returns
+    ///
+    /// A `String` containing the processed line.
+    fn render_line(&mut self, line: &Line) -> String {
+        let mut result = String::new();
+        for inline in &line.inlines {
+            match inline {
+                Inline::Text(text) => result.push_str(text),
+                Inline::Expr { code, span } => {
+                    let budget = self.budget();
+                    match Self::process_lambda(
+                        &self.rhai_engine,
+                        &mut self.rhai_scope,
+                        budget,
+                        code,
+                    ) {
+                        Ok(value) => result.push_str(&value),
+                        Err(err) => self.inline_error(*err, *span, line.span.column.max(1)),
+                    }
+                }
             }
         }
+
+        if result.trim().is_empty() {
+            "\n".to_string() // Always return a newline for empty lines
+        } else {
+            format!("{}\n", result) // Add a newline after each non-empty line
+        }
+    }
+
+    /// Records a failed inline expression, the expression renders as nothing.
+    ///
+    /// # Arguments
+    ///
+    /// * `err` - The error returned by the Rhai engine.
+    /// * `span` - The position of the expression.
+    /// * `margin` - The column the lines of the expression's line start at.
+    fn inline_error(&mut self, err: rhai::EvalAltResult, span: Span, margin: usize) {
+        // The code starts after the opening delimiter
+        let column = span.column + self.delimiters.open().chars().count();
+        self.diagnostics.push(Diagnostic::from_script_error(
+            err,
+            format!("{}...{}", self.delimiters.open(), self.delimiters.close()),
+            span,
+            span.line,
+            column,
+            margin,
+        ));
     }
 }
 
+/// Puts a container prefix, such as the indentation of a list item or the
+/// `>` of a block quote, in front of every line of rendered markdown.
+///
+/// Blank lines only get the `>` markers, followed by a space so that they
+/// are not read as greentext.
+fn prefix_lines(text: &str, prefix: &str) -> String {
+    let quoted = prefix.contains('>');
+    text.split_inclusive('\n')
+        .map(|line| match line.trim() {
+            "" if !quoted => line.to_string(),
+            "" => format!(
+                "{} {}",
+                prefix.trim_end(),
+                line.trim_start_matches([' ', '\t'])
+            ),
+            _ => format!("{}{}", prefix, line),
+        })
+        .collect()
+}
+
+/// Reads a line holding only a markdown image, `![alt](src "title")`.
+///
+/// # Returns
+///
+/// The image's source and alt text.
+fn parse_image(text: &str) -> Option<(String, String)> {
+    let (alt, rest) = text.trim().strip_prefix("![")?.split_once("](")?;
+    let target = rest.strip_suffix(')')?.trim();
+    let src = target.split_once(' ').map_or(target, |(src, _)| src);
+    (!src.is_empty()).then(|| (src.to_string(), alt.to_string()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::diagnostic::Severity;
     use std::fs;
 
     /*
@@ -382,13 +886,13 @@ mod tests {
         // Test numeric function - note the \n at the end
         let input1 = "Result: λ#(double(21))#";
         let expected1 = "Result: 42"; // New lines are stripped
-        let result1 = processor.process(input1);
+        let result1 = processor.process(input1).output;
         assert_eq!(result1, expected1);
 
         // Test string function
         let input2 = r#"Combined: λ#(concat("Hello ", "World"))#"#;
         let expected2 = "Combined: Hello World"; // New lines are stripped
-        let result2 = processor.process(input2);
+        let result2 = processor.process(input2).output;
         assert_eq!(result2, expected2);
     }
 
@@ -405,7 +909,7 @@ mod tests {
                 .trim_end_matches('\n')
                 .to_string();
 
-        let result = processor.process(&test_string);
+        let result = processor.process(&test_string).output;
 
         assert_eq!(
             result, expected,
@@ -422,7 +926,7 @@ mod tests {
 
         let expected = test_string.clone().trim_end_matches('\n').to_string();
 
-        let result = processor.process(&test_string);
+        let result = processor.process(&test_string).output;
 
         assert_eq!(
             expected, result,
@@ -489,7 +993,7 @@ mod tests {
         // Processor is Mutable as it stores the rhai environment scope
         let mut processor = Processor::new(Some(functions));
 
-        let result = processor.process(&test_string);
+        let result = processor.process(&test_string).output;
 
         assert_eq!(
             result, expected,
@@ -497,6 +1001,110 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_diagnostics() {
+        let input = "Value: λ#(missing)#\n\n```{rhai}\nlet x = 1;\nx +\n```\n\n:::tip\nUnclosed";
+        let mut processor = Processor::default();
+        let rendered = processor.process(input);
+
+        assert_eq!(
+            rendered.output,
+            "Value: \n\n\n<div class=\"admonition tip\">\nUnclosed\n</div>"
+        );
+
+        let positions: Vec<_> = rendered
+            .diagnostics
+            .iter()
+            .map(|d| (d.severity, d.line, d.column, d.directive.as_str()))
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                (Severity::Error, 1, 11, "λ#(...)#"),
+                (Severity::Error, 5, 4, "```{rhai}"),
+                (Severity::Warning, 8, 1, ":::tip"),
+            ]
+        );
+        assert_eq!(
+            rendered.diagnostics[0].message,
+            "Variable not found: missing"
+        );
+    }
+
+    #[test]
+    fn test_custom_admonitions() {
+        let registry = AdmonitionRegistry::default()
+            .with("danger", "<div class=\"callout {name}\">", "</div>")
+            .with("tip", "<aside class=\"hint\">", "</aside>");
+        let mut processor = Processor::default().with_admonitions(registry);
+
+        let result = processor
+            .process(":::danger\nCareful\n:::\n:::tip\nHint\n:::")
+            .output;
+
+        assert_eq!(
+            result,
+            "<div class=\"callout danger\">\nCareful\n</div>\n<aside class=\"hint\">\nHint\n</aside>"
+        );
+    }
+
+    #[test]
+    fn test_style_backends() {
+        let input = ":::tip\nHint\n:::\n:::tabs\n:::tab\nOne\n:::\n:::";
+
+        let bootstrap = Processor::default()
+            .with_style(StyleBackend::Bootstrap)
+            .process(input)
+            .output;
+        assert_eq!(
+            bootstrap,
+            r##"<div class="alert alert-primary">
+Hint
+</div>
+<ul class="nav nav-tabs" role="tablist">
+  <li class="nav-item" role="presentation"><button class="nav-link active" data-bs-toggle="tab" data-bs-target="#tabs-1-1" type="button" role="tab" aria-selected="true">Tab 1</button></li>
+</ul>
+<div class="tab-content">
+  <div class="tab-pane fade show active" id="tabs-1-1" role="tabpanel">
+One
+  </div>
+</div>"##
+        );
+
+        let semantic = Processor::default()
+            .with_style(StyleBackend::Semantic)
+            .process(input)
+            .output;
+        assert_eq!(
+            semantic,
+            r#"<aside class="tip">
+Hint
+</aside>
+<section class="tabs">
+  <details name="tabs-1" open><summary>Tab 1</summary>
+One
+  </details>
+</section>"#
+        );
+    }
+
+    #[test]
+    fn test_admonition_titles_and_attributes() {
+        let input =
+            ":::warning Back up first\nText\n:::\n:::tip {#id .extra-class data-x=1}\nText\n:::";
+        let mut processor = Processor::default();
+
+        assert_eq!(
+            processor.process(input).output,
+            r#"<div class="admonition important"><p class="admonition-title">Back up first</p>
+Text
+</div>
+<div class="admonition tip extra-class" id="id" data-x="1">
+Text
+</div>"#
+        );
+    }
+
     #[test]
     fn test_tabs_processing() {
         let input = r#":::tabs
@@ -517,24 +1125,24 @@ Tab content 3
 
         let expected_output = r#"<div role="tablist" class="tabs tabs-lifted">
 
-  <input type="radio" name="my_tabs_2" role="tab" class="tab" aria-label="Tab 1"/>
+  <input type="radio" name="tabs-1" role="tab" class="tab" aria-label="Tab 1" checked="checked"/>
   <div role="tabpanel" class="tab-content bg-base-100 border-base-300 rounded-box p-6">
 Tab content 1
   </div>
 
-  <input type="radio" name="my_tabs_2" role="tab" class="tab" aria-label="Tab 2" checked="checked"/>
+  <input type="radio" name="tabs-1" role="tab" class="tab" aria-label="Tab 2"/>
   <div role="tabpanel" class="tab-content bg-base-100 border-base-300 rounded-box p-6">
 Tab content 2
   </div>
 
-  <input type="radio" name="my_tabs_2" role="tab" class="tab" aria-label="Tab 3"/>
+  <input type="radio" name="tabs-1" role="tab" class="tab" aria-label="Tab 3"/>
   <div role="tabpanel" class="tab-content bg-base-100 border-base-300 rounded-box p-6">
 Tab content 3
   </div>
 </div>"#;
 
         let mut processor = Processor::default();
-        let result = processor.process(input);
+        let result = processor.process(input).output;
 
         assert_eq!(
             result.trim(),
@@ -543,6 +1151,494 @@ Tab content 3
         );
     }
 
+    #[test]
+    fn test_titled_tabs() {
+        let input = r#":::tabs 2
+
+:::tab First
+Tab content 1
+:::
+
+:::tab Second
+Tab content 2
+:::
+
+:::tab
+Tab content 3
+:::
+
+:::"#;
+
+        let expected_output = r#"<div role="tablist" class="tabs tabs-lifted">
+
+  <input type="radio" name="tabs-1" role="tab" class="tab" aria-label="First"/>
+  <div role="tabpanel" class="tab-content bg-base-100 border-base-300 rounded-box p-6">
+Tab content 1
+  </div>
+
+  <input type="radio" name="tabs-1" role="tab" class="tab" aria-label="Second" checked="checked"/>
+  <div role="tabpanel" class="tab-content bg-base-100 border-base-300 rounded-box p-6">
+Tab content 2
+  </div>
+
+  <input type="radio" name="tabs-1" role="tab" class="tab" aria-label="Tab 3"/>
+  <div role="tabpanel" class="tab-content bg-base-100 border-base-300 rounded-box p-6">
+Tab content 3
+  </div>
+</div>"#;
+
+        let mut processor = Processor::default();
+        let result = processor.process(input).output;
+
+        assert_eq!(
+            result.trim(),
+            expected_output.trim(),
+            "Titled tabs did not produce the expected output"
+        );
+    }
+
+    #[test]
+    fn test_tab_groups() {
+        let input = r#":::tabs
+:::tab A
+:::
+:::tab B
+:::
+:::tab C
+:::
+:::tab D "quoted"
+:::
+:::
+:::tabs 5
+:::tab Only
+:::
+:::"#;
+
+        let mut processor = Processor::default();
+        let rendered = processor.process(input);
+        let radios: Vec<&str> = rendered
+            .output
+            .lines()
+            .filter(|line| line.contains("type=\"radio\""))
+            .collect();
+
+        assert_eq!(radios.len(), 5);
+        assert!(radios[..4].iter().all(|r| r.contains("name=\"tabs-1\"")));
+        assert!(radios[0].contains("checked"));
+        assert!(radios[3].contains("aria-label=\"D &quot;quoted&quot;\""));
+        assert!(radios[4].contains("name=\"tabs-2\"") && radios[4].contains("checked"));
+        assert_eq!(
+            rendered.diagnostics[0].message,
+            "`5` is not a tab number between 1 and 1"
+        );
+    }
+
+    #[test]
+    fn test_tab_attributes() {
+        let input = ":::tabs\n:::tab Setup {#setup .wide data-step=1}\nOne\n:::\n:::";
+
+        let daisyui = Processor::default().process(input).output;
+        assert!(daisyui.contains(
+            "<div role=\"tabpanel\" class=\"tab-content bg-base-100 border-base-300 rounded-box p-6 wide\" id=\"setup\" data-step=\"1\">"
+        ));
+        assert!(daisyui.contains("aria-label=\"Setup\" checked=\"checked\"/>"));
+
+        // The tab list refers to the pane by the id it was given
+        let bootstrap = Processor::default()
+            .with_style(StyleBackend::Bootstrap)
+            .process(input)
+            .output;
+        assert!(bootstrap.contains("data-bs-target=\"#setup\""));
+        assert!(bootstrap.contains(
+            "<div class=\"tab-pane fade show active wide\" id=\"setup\" role=\"tabpanel\" data-step=\"1\">"
+        ));
+    }
+
+    #[test]
+    fn test_fold_summary() {
+        let input = r#":::fold
+Hidden
+:::
+:::fold Click to see the proof {open}
+:::fold Step 1
+QED
+:::
+:::
+:::fold
+:::summary
+**Details**
+:::
+Body
+:::"#;
+
+        let mut processor = Processor::default();
+        assert_eq!(
+            processor.process(input).output,
+            r#"<details class="my-details"><summary>📂</summary>
+Hidden
+</details>
+<details class="my-details" open><summary>Click to see the proof</summary>
+<details class="my-details"><summary>Step 1</summary>
+QED
+</details>
+</details>
+<details class="my-details"><summary class="my-summary">
+**Details**
+</summary>
+Body
+</details>"#
+        );
+
+        // A summary written last still comes first
+        assert_eq!(
+            processor
+                .process(":::fold\nBody\n:::summary\nS\n:::\n:::")
+                .output,
+            "<details class=\"my-details\"><summary class=\"my-summary\">\nS\n</summary>\nBody\n</details>"
+        );
+    }
+
+    #[test]
+    fn test_nested_tabs_and_admonitions() {
+        let input = r#":::tip
+:::tabs
+:::tab Outer
+:::warning
+:::tabs
+:::tab Inner A
+:::fold
+λ#(1 + 1)#
+:::
+:::
+:::tab Inner B
+:::
+:::
+:::
+:::
+:::tab Second
+:::tip
+Deep
+:::
+:::
+:::
+After
+:::"#;
+
+        let mut processor = Processor::default();
+        let rendered = processor.process(input);
+        let output = &rendered.output;
+
+        assert!(
+            rendered.diagnostics.is_empty(),
+            "{:?}",
+            rendered.diagnostics
+        );
+        assert_eq!(output.matches("<div class=\"admonition tip\">").count(), 2);
+        assert_eq!(output.matches("name=\"tabs-1\"").count(), 2);
+        assert_eq!(output.matches("name=\"tabs-2\"").count(), 2);
+        assert_eq!(output.matches("<div role=\"tablist\"").count(), 2);
+        assert_eq!(output.matches("<details").count(), 1);
+
+        // Every element is closed in the order it was opened
+        let fold = output.find("<details").unwrap();
+        let value = output.find("\n2\n").unwrap();
+        let fold_end = output.find("</details>").unwrap();
+        let inner_b = output.find("aria-label=\"Inner B\"").unwrap();
+        let second = output.find("aria-label=\"Second\"").unwrap();
+        let deep = output.find("Deep").unwrap();
+        let after = output.find("After").unwrap();
+        assert!(fold < value && value < fold_end && fold_end < inner_b);
+        assert!(inner_b < second && second < deep && deep < after);
+        assert!(output.ends_with("After\n</div>"));
+        assert_eq!(
+            output.matches("<div").count(),
+            output.matches("</div>").count()
+        );
+    }
+
+    #[test]
+    fn test_pandoc_and_myst_syntax() {
+        let mut processor = Processor::default().with_syntax(Syntax::Pandoc);
+        assert_eq!(
+            processor
+                .process("::: {.warning #backup}\nText\n:::")
+                .output,
+            "<div class=\"admonition important\" id=\"backup\">\nText\n</div>"
+        );
+
+        let mut processor = Processor::default().with_syntax(Syntax::Myst);
+        assert_eq!(
+            processor
+                .process("```{warning} Back up first\n:name: backup\nText\n```")
+                .output,
+            "<div class=\"admonition important\" id=\"backup\"><p class=\"admonition-title\">Back up first</p>\nText\n</div>"
+        );
+
+        // Docutils kinds render like the GitHub alerts of the same name
+        let note = "<div class=\"admonition note\">\nText\n</div>";
+        let mut processor = Processor::default().with_syntax(Syntax::Pandoc);
+        assert_eq!(processor.process("::: note\nText\n:::").output, note);
+        let mut processor = Processor::default().with_syntax(Syntax::Myst);
+        assert_eq!(processor.process(":::{note}\nText\n:::").output, note);
+        assert_eq!(
+            processor.process("```{danger}\nText\n```").output,
+            "<div class=\"admonition warning\">\nText\n</div>"
+        );
+
+        // A div without a class keeps only the attributes it was given
+        let mut processor = Processor::default().with_syntax(Syntax::Pandoc);
+        assert_eq!(
+            processor
+                .process("::: {#only-id data-x=1}\nText\n:::")
+                .output,
+            "<div id=\"only-id\" data-x=\"1\">\nText\n</div>"
+        );
+
+        // A registered admonition takes precedence over the alias
+        let admonitions =
+            AdmonitionRegistry::default().with("danger", "<div class=\"danger\">", "</div>");
+        let mut processor = Processor::default()
+            .with_syntax(Syntax::Pandoc)
+            .with_admonitions(admonitions);
+        assert_eq!(
+            processor.process("::: danger\nText\n:::").output,
+            "<div class=\"danger\">\nText\n</div>"
+        );
+        assert_eq!(processor.process("::: note\nText\n:::").output, note);
+    }
+
+    #[test]
+    fn test_custom_delimiters() {
+        let input = "A `r 6 *\n7` B `code`\n\n`r x`";
+        let mut processor = Processor::default().with_delimiters(Delimiters::rmarkdown());
+        let rendered = processor.process(input);
+
+        assert_eq!(rendered.output, "A 42 B `code`");
+        assert_eq!(rendered.diagnostics[0].directive, "`r ...`");
+        assert_eq!(
+            (rendered.diagnostics[0].line, rendered.diagnostics[0].column),
+            (4, 4)
+        );
+    }
+
+    #[test]
+    fn test_no_expansion_in_code() {
+        let input = "Use `λ#(x)#` or \\λ#(x)#, not λ#(1 + 1)#\n```markdown\nλ#(1 + 1)#\n```\n```markdown {expand}\nλ#(1 + 1)#\n```";
+        let mut processor = Processor::default();
+        let rendered = processor.process(input);
+
+        assert_eq!(
+            rendered.output,
+            "Use `λ#(x)#` or λ#(x)#, not 2\n```markdown\nλ#(1 + 1)#\n```\n```markdown\n2\n```"
+        );
+        assert!(rendered.diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_columns() {
+        let input = r#":::columns md
+:::column 1/3
+Left
+:::
+:::column
+Right
+:::
+:::"#;
+
+        let mut processor = Processor::default();
+        assert_eq!(
+            processor.process(input).output,
+            r#"<div class="flex w-full flex-col gap-4 md:flex-row">
+  <div class="md:w-1/3">
+Left
+  </div>
+  <div class="flex-1">
+Right
+  </div>
+</div>"#
+        );
+
+        let mut processor = Processor::default().with_style(StyleBackend::Bootstrap);
+        let rendered =
+            processor.process(":::columns\n:::column 40%\n:::\n:::column half\n:::\n:::");
+        assert_eq!(
+            rendered.output,
+            "<div class=\"row\">\n  <div class=\"col-lg-5\">\n  </div>\n  <div class=\"col-lg\">\n  </div>\n</div>"
+        );
+        assert_eq!(
+            rendered.diagnostics[0].message,
+            "`half` is not a column width like `1/3` or `40%`"
+        );
+
+        let mut processor = Processor::default().with_style(StyleBackend::Semantic);
+        assert_eq!(
+            processor
+                .process(":::columns sm {.gallery}\n:::column 1/4\n:::\n:::")
+                .output,
+            "<div class=\"columns gallery\" data-breakpoint=\"sm\">\n  <div class=\"column\" style=\"flex-basis: 25%\">\n  </div>\n</div>"
+        );
+    }
+
+    #[test]
+    fn test_cards() {
+        let input = r#":::cards 2
+:::card Getting started {.featured}
+![Logo](media/logo.png "The logo")
+Read this first.
+:::actions
+[Open](start.md)
+:::
+:::
+:::card {title="Reference" img=ref.png alt=Books}
+Everything else.
+:::
+:::"#;
+
+        let mut processor = Processor::default();
+        assert_eq!(
+            processor.process(input).output,
+            r#"<div class="grid grid-cols-1 gap-4 md:grid-cols-2 lg:grid-cols-2">
+<div class="card bg-base-100 shadow-xl featured">
+<figure><img src="media/logo.png" alt="Logo" /></figure>
+<div class="card-body">
+<h2 class="card-title">Getting started</h2>
+
+Read this first.
+
+<div class="card-actions justify-end">
+
+[Open](start.md)
+
+</div>
+</div>
+</div>
+<div class="card bg-base-100 shadow-xl">
+<figure><img src="ref.png" alt="Books" /></figure>
+<div class="card-body">
+<h2 class="card-title">Reference</h2>
+
+Everything else.
+
+</div>
+</div>
+</div>"#
+        );
+
+        let mut processor = Processor::default().with_style(StyleBackend::Bootstrap);
+        assert_eq!(
+            processor
+                .process(":::cards\n:::card Plain\nText\n:::footer\nBye\n:::\n:::\n:::")
+                .output,
+            r#"<div class="row row-cols-1 row-cols-md-2 row-cols-lg-3 g-4">
+<div class="col">
+<div class="card">
+<div class="card-body">
+<h5 class="card-title">Plain</h5>
+
+Text
+
+</div>
+<div class="card-footer">
+
+Bye
+
+</div>
+</div>
+</div>
+</div>"#
+        );
+    }
+
+    #[test]
+    fn test_tab_outside_tabs() {
+        let mut processor = Processor::default();
+        let rendered = processor.process(":::tab Lost\nText\n:::");
+
+        assert_eq!(
+            rendered.output,
+            "<div class=\"tab\"><p class=\"admonition-title\">Lost</p>\nText\n</div>"
+        );
+        assert_eq!(
+            rendered.diagnostics[0].message,
+            "`:::tab` is not inside a `:::tabs` block"
+        );
+    }
+
+    #[test]
+    fn test_directives_in_lists_and_quotes() {
+        let input = "- item\n  :::tip\n  λ#(1 + 1)#\n\n  ```{rhai-display}\n  3\n  ```\n  :::\n\n> :::warning\n>\n> text\n> :::";
+        let result = Processor::default().process(input);
+
+        assert!(result.diagnostics.is_empty(), "{:?}", result.diagnostics);
+        assert_eq!(
+            result.output,
+            r#"- item
+  <div class="admonition tip">
+  2
+
+  <div class="rhai-display">
+
+  ```rust
+  3
+  ```
+  <div class="rhai-out">
+
+  ```
+  3
+  ```
+  </div>
+  </div>
+  </div>
+
+> <div class="admonition important">
+> 
+> text
+> </div>"#
+        );
+    }
+
+    #[test]
+    fn test_errors_in_lists_and_quotes() {
+        let input = "> :::tip\n> ```{rhai}\n> let x = 1 +;\n> ```\n> :::\n\n- item\n  ```{rhai}\n  let a = 1;\n  a + b\n  ```\n\n> :::note\n> λ#(1 +\n> missing)#\n> :::";
+        let rendered = Processor::default().process(input);
+        let positions: Vec<_> = rendered
+            .diagnostics
+            .iter()
+            .map(|d| (d.line, d.column))
+            .collect();
+
+        assert_eq!(positions, [(3, 14), (10, 7), (15, 3)]);
+    }
+
+    #[test]
+    fn test_script_limits() {
+        let limits = ScriptLimits {
+            max_operations: Some(1000),
+            max_string_size: Some(100),
+            ..ScriptLimits::default()
+        };
+        let mut processor = Processor::default().with_limits(limits);
+        let input = "```{rhai}\nloop {}\n```\n\nλ#(let s = \"ab\"; loop { s += s })# λ#(1 + 1)#";
+        let result = processor.process(input);
+
+        assert_eq!(result.output, "\n 2");
+        let messages: Vec<_> = result
+            .diagnostics
+            .iter()
+            .map(|d| d.message.as_str())
+            .collect();
+        assert_eq!(messages[0], "Script exceeded the limit of 1000 operations");
+        assert!(messages[1].contains("too large"), "{}", messages[1]);
+
+        // The deadline covers the whole document
+        let mut processor = Processor::default().with_limits(ScriptLimits {
+            timeout: Some(std::time::Duration::ZERO),
+            ..ScriptLimits::none()
+        });
+        let result = processor.process("λ#(loop {})#\n\nλ#(let x = 0; while x < 1000 { x += 1 })#");
+        assert_eq!(result.diagnostics.len(), 2);
+    }
+
     #[test]
     fn test_list_tems() {
         let input = r#"
@@ -558,9 +1654,9 @@ Tab content 3
 "#;
 
         let input = input.trim();
-        let expected_output = input.clone();
+        let expected_output = input;
         let mut processor = Processor::default();
-        let result = processor.process(input);
+        let result = processor.process(input).output;
 
         assert_eq!(
             result.trim(),
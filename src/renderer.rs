@@ -0,0 +1,458 @@
+//! A long-lived renderer that keeps its expensive setup between documents.
+
+use crate::admonition::AdmonitionRegistry;
+use crate::alert::{render_alerts, render_callouts};
+use crate::error::RenderError;
+use crate::extension::DraftsmithExtension;
+use crate::options::RenderOptions;
+use crate::parser::{Delimiters, Syntax};
+use crate::processor::{CustomFn, Processor, Rendered};
+use crate::script::{EngineSettings, Prelude, ScriptLimits, ScriptPackages};
+use crate::style::StyleBackend;
+use comrak::nodes::NodeValue;
+// https://raw.githubusercontent.com/kivikakk/comrak/f4853af61978e90d73f3b8c9a63be186d85c1e5c/examples/syntect.rs
+use comrak::plugins::syntect::{SyntectAdapter, SyntectAdapterBuilder};
+use comrak::{format_html_with_plugins, parse_document, Arena, Plugins};
+use rhai::Engine;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Renders many documents with the same options and Rhai functions.
+///
+/// Loading the syntax highlighting themes and setting up the Rhai engine is
+/// much slower than rendering a typical note, so a `Renderer` does it once.
+/// Every document still gets its own Rhai scope, so variables do not leak
+/// from one document to the next.
+///
+/// A `Renderer` is `Send + Sync` and can be shared between threads, e.g.
+/// behind an [`Arc`].
+pub struct Renderer {
+    options: RenderOptions,
+    adapter: SyntectAdapter,
+    engine: Arc<Engine>,
+    engine_settings: EngineSettings,
+    admonitions: Arc<AdmonitionRegistry>,
+    style: StyleBackend,
+    syntax: Syntax,
+    delimiters: Delimiters,
+}
+
+impl Renderer {
+    /// Creates a renderer with optional Rhai function registrations.
+    ///
+    /// # Arguments
+    ///
+    /// * `functions` - Optional Rhai function registrations.
+    /// * `options` - The markdown extensions and rendering flags to use.
+    pub fn new(functions: Option<Vec<CustomFn>>, options: RenderOptions) -> Self {
+        let engine_settings = EngineSettings::new(functions);
+        Self {
+            options,
+            adapter: SyntectAdapterBuilder::new()
+                .theme("base16-ocean.dark")
+                .build(),
+            engine: Arc::new(engine_settings.build()),
+            engine_settings,
+            admonitions: Arc::new(AdmonitionRegistry::default()),
+            style: StyleBackend::default(),
+            syntax: Syntax::default(),
+            delimiters: Delimiters::default(),
+        }
+    }
+
+    /// Produces markup for `style`.
+    ///
+    /// This also replaces the admonitions with the built-in ones of `style`,
+    /// so call [`Renderer::with_admonitions`] afterwards to customise them.
+    pub fn with_style(mut self, style: StyleBackend) -> Self {
+        self.style = style;
+        self.admonitions = Arc::new(style.admonitions());
+        self
+    }
+
+    /// Uses `admonitions` for the markup of `:::name` blocks, either a
+    /// registry or one already shared behind an [`Arc`].
+    pub fn with_admonitions(mut self, admonitions: impl Into<Arc<AdmonitionRegistry>>) -> Self {
+        self.admonitions = admonitions.into();
+        self
+    }
+
+    /// Also accepts the directives of `syntax`, e.g. pandoc fenced divs.
+    pub fn with_syntax(mut self, syntax: Syntax) -> Self {
+        self.syntax = syntax;
+        self
+    }
+
+    /// Uses `delimiters` around inline expressions instead of `λ#(` and `)#`.
+    pub fn with_delimiters(mut self, delimiters: Delimiters) -> Self {
+        self.delimiters = delimiters;
+        self
+    }
+
+    /// Evaluates scripts within `limits` instead of the default ones.
+    pub fn with_limits(mut self, limits: ScriptLimits) -> Self {
+        self.engine_settings.limits = limits;
+        self.engine = Arc::new(self.engine_settings.build());
+        self
+    }
+
+    /// Gives scripts the functions of `packages` instead of the core and
+    /// math ones.
+    pub fn with_packages(mut self, packages: ScriptPackages) -> Self {
+        self.engine_settings.packages = packages;
+        self.engine = Arc::new(self.engine_settings.build());
+        self
+    }
+
+    /// Resolves `import "helpers/finance" as fin;` in chunks and inline
+    /// expressions to the file `helpers/finance.rhai` in `directory`.
+    ///
+    /// Imports may not reach outside the directory. Each module is compiled
+    /// the first time it is imported and then shared by every document this
+    /// renderer renders, so changes to a module file need a new renderer.
+    /// An import lasts for the chunk or expression it is written in.
+    pub fn with_modules(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.engine_settings.modules = Some(directory.into());
+        self.engine = Arc::new(self.engine_settings.build());
+        self
+    }
+
+    /// Makes the functions and constants of `prelude` available to every
+    /// chunk and inline expression. Call it again for more preludes.
+    pub fn with_prelude(mut self, prelude: Prelude) -> Self {
+        self.engine_settings.preludes.push(prelude);
+        self.engine = Arc::new(self.engine_settings.build());
+        self
+    }
+
+    /// Registers the functions, types and constants of `extension`. Call it
+    /// again for more extensions.
+    pub fn with_extension(mut self, extension: impl DraftsmithExtension + 'static) -> Self {
+        self.engine_settings.extensions.push(Box::new(extension));
+        self.engine = Arc::new(self.engine_settings.build());
+        self
+    }
+
+    /// The extensions registered with this renderer, in the order they were
+    /// added.
+    pub fn extensions(&self) -> impl Iterator<Item = &dyn DraftsmithExtension> {
+        self.engine_settings
+            .extensions
+            .iter()
+            .map(|extension| extension.as_ref())
+    }
+
+    /// The markup used for `:::name` blocks.
+    pub fn admonitions(&self) -> &AdmonitionRegistry {
+        &self.admonitions
+    }
+
+    /// The options documents are rendered with.
+    pub fn options(&self) -> &RenderOptions {
+        &self.options
+    }
+
+    /// Creates a processor for one document, sharing this renderer's engine.
+    pub fn processor(&self) -> Processor<'static> {
+        Processor::with_engine(Arc::clone(&self.engine))
+            .with_style(self.style)
+            .with_admonitions(Arc::clone(&self.admonitions))
+            .with_syntax(self.syntax)
+            .with_delimiters(self.delimiters.clone())
+            .with_limits(self.engine_settings.limits)
+    }
+
+    /// Expands the custom syntax of a document, leaving it as markdown.
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - A string slice that holds the Markdown content.
+    ///
+    /// # Returns
+    ///
+    /// A [`Rendered`] containing the processed markdown and its diagnostics.
+    pub fn process(&self, document: &str) -> Rendered {
+        self.processor().process(document)
+    }
+
+    /// Parses a Markdown document and converts it to HTML.
+    ///
+    /// # Arguments
+    ///
+    /// * `document` - A string slice that holds the Markdown content to be parsed.
+    ///
+    /// # Returns
+    ///
+    /// A [`Rendered`] containing the HTML output and diagnostics, or the error
+    /// that stopped rendering.
+    ///
+    /// # Process
+    ///
+    /// 1. Creates an Arena for memory management.
+    /// 2. Converts the [`RenderOptions`] to Comrak options.
+    /// 3. Preprocesses the document using a custom `Processor`.
+    /// 4. Parses the preprocessed document into an AST.
+    /// 5. Iterates over the AST, applying custom transformations (e.g., for math elements).
+    /// 6. Turns `> [!NOTE]` alerts or Obsidian callouts into admonitions, if
+    ///    enabled.
+    /// 7. Formats the modified AST into HTML.
+    pub fn render(&self, document: &str) -> Result<Rendered, RenderError> {
+        // Set up plugins for syntax highlighting
+        let mut plugins = Plugins::default();
+        plugins.render.codefence_syntax_highlighter = Some(&self.adapter);
+
+        // The returned nodes are created in the supplied Arena, and are bound by its lifetime.
+        let arena = Arena::new();
+
+        // Configure the options
+        let options = self.options.comrak_options();
+
+        // Preprocess the document
+        let Rendered {
+            output: document,
+            diagnostics,
+        } = self.process(document);
+        let document = document.as_str();
+
+        // get the AST
+        let root = parse_document(&arena, document, &options);
+
+        // Iterate over all the descendants of root.
+        for node in root.descendants() {
+            /*
+            // Left in for reference
+            if let NodeValue::Text(ref mut text) = node.data.borrow_mut().value {
+                // If the node is a text node, perform the string replacement.
+                *text = text.replace(orig_string, replacement);
+            }
+            */
+
+            // handle math (efficiently)
+            if let NodeValue::Math(ref mut math) = node.data.borrow_mut().value {
+                // Capture the current math literal
+                let math_literal = &mut math.literal;
+
+                // Determine the appropriate prefix and calculate the total length upfront
+                let prefix = if math.display_math { "$$" } else { "$" };
+                let prefix_len = prefix.len();
+                let total_len = prefix_len + math_literal.len() + prefix_len;
+
+                // Reserve the capacity to avoid multiple allocations
+                math_literal.reserve_exact(total_len - math_literal.len());
+
+                // Use `insert_str` to prepend and append the prefix directly to the literal
+                math_literal.insert_str(0, prefix);
+                math_literal.push_str(prefix);
+            }
+        }
+
+        if self.options.obsidian_callouts {
+            render_callouts(&arena, root, &self.admonitions);
+        } else if self.options.github_alerts {
+            render_alerts(&arena, root, &self.admonitions);
+        }
+
+        let mut html = vec![];
+        format_html_with_plugins(root, &options, &mut html, &plugins)
+            .map_err(RenderError::Format)?;
+        Ok(Rendered {
+            output: String::from_utf8(html)?,
+            diagnostics,
+        })
+    }
+}
+
+impl Default for Renderer {
+    fn default() -> Self {
+        Self::new(None, RenderOptions::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_renderer_is_send_sync() {
+        assert_send_sync::<Renderer>();
+    }
+
+    #[test]
+    fn test_documents_do_not_share_scope() {
+        let renderer = Renderer::default();
+
+        let first = renderer.process("```{rhai}\nlet x = 1;\n```\nλ#(x)#");
+        let second = renderer.process("λ#(x)#");
+
+        assert_eq!(first.output, "1");
+        assert_eq!(second.output, "");
+        assert_eq!(second.diagnostics[0].message, "Variable not found: x");
+    }
+
+    #[test]
+    fn test_github_alerts() {
+        let renderer = Renderer::default();
+        let document = "> [!NOTE]\n> Useful *information*.\n\n> [!unknown]\n> Kept\n\n> [!Warning]\n> \n> Two paragraphs";
+
+        assert_eq!(
+            renderer.render(document).unwrap().output,
+            r#"<div class="admonition note"><p class="admonition-title">Note</p>
+<p>Useful <em>information</em>.</p>
+</div>
+<blockquote>
+<p>[!unknown]
+Kept</p>
+</blockquote>
+<div class="admonition important"><p class="admonition-title">Warning</p>
+<p>Two paragraphs</p>
+</div>
+"#
+        );
+
+        let renderer = Renderer::new(None, RenderOptions::builder().github_alerts(false).build());
+        assert!(renderer
+            .render("> [!TIP]\n> Text")
+            .unwrap()
+            .output
+            .starts_with("<blockquote>"));
+    }
+
+    #[test]
+    fn test_obsidian_callouts() {
+        let renderer = Renderer::new(
+            None,
+            RenderOptions::builder().obsidian_callouts(true).build(),
+        );
+        let document = "> [!faq]- Why *this*?\n> Because.\n> \n> > [!custom]+\n> > Nested\n\n> [!tip] Custom title\n> Text";
+
+        assert_eq!(
+            renderer.render(document).unwrap().output,
+            r#"<div role="alert" class="alert alert-info callout-faq"><details class="my-details"><summary>Why this?</summary>
+<p>Because.</p>
+<div class="callout callout-custom"><details class="my-details" open><summary>Custom</summary>
+<p>Nested</p>
+</details></div>
+</details></div>
+<div class="admonition tip callout-tip"><p class="admonition-title">Custom title</p>
+<p>Text</p>
+</div>
+"#
+        );
+    }
+
+    #[test]
+    fn test_cards() {
+        let renderer = Renderer::default();
+        let document =
+            ":::cards\n:::card Title\nBody *em*\n:::actions\n[Open](start.md)\n:::\n:::\n:::";
+
+        assert_eq!(
+            renderer.render(document).unwrap().output,
+            r#"<div class="grid grid-cols-1 gap-4 md:grid-cols-2 lg:grid-cols-3">
+<div class="card bg-base-100 shadow-xl">
+<div class="card-body">
+<h2 class="card-title">Title</h2>
+<p>Body <em>em</em></p>
+<div class="card-actions justify-end">
+<p><a href="start.md">Open</a></p>
+</div>
+</div>
+</div>
+</div>
+"#
+        );
+    }
+
+    #[test]
+    fn test_render_across_threads() {
+        fn triple(x: i64) -> i64 {
+            x * 3
+        }
+        let functions: Vec<CustomFn> = vec![Box::new(|engine: &mut Engine| {
+            engine.register_fn("triple", triple);
+        })];
+        let renderer = Arc::new(Renderer::new(Some(functions), RenderOptions::default()));
+
+        let handles: Vec<_> = (1..=4)
+            .map(|i| {
+                let renderer = Arc::clone(&renderer);
+                thread::spawn(move || renderer.render(&format!("λ#(triple({i}))#")))
+            })
+            .collect();
+
+        for (i, handle) in (1..=4).zip(handles) {
+            let rendered = handle.join().unwrap().expect("Rendering should not fail");
+            assert_eq!(rendered.output, format!("<p>{}</p>\n", i * 3));
+        }
+    }
+
+    #[test]
+    fn test_modules() {
+        let renderer = Renderer::default().with_modules("tests/fixtures/scripts");
+        let document = "```{rhai}\nimport \"helpers/finance\" as fin;\nlet total = fin::price(50);\n```\n\nλ#(total)# λ#(import \"helpers/finance\" as fin; fin::VAT)#";
+        for _ in 0..2 {
+            let rendered = renderer.process(document);
+            assert!(
+                rendered.diagnostics.is_empty(),
+                "{:?}",
+                rendered.diagnostics
+            );
+            assert_eq!(rendered.output, "\n$60 20");
+        }
+
+        let rendered = renderer
+            .process("λ#(import \"../outside\" as o; o::SECRET)#\nλ#(import \"missing\" as m; 1)#");
+        let messages: Vec<_> = rendered
+            .diagnostics
+            .iter()
+            .map(|d| d.message.as_str())
+            .collect();
+        assert_eq!(
+            messages,
+            [
+                "Runtime error: Module `../outside` is outside the modules directory",
+                "Module not found: missing"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extensions() {
+        use crate::extension::Registrar;
+
+        struct Greeting(&'static str);
+
+        impl DraftsmithExtension for Greeting {
+            fn name(&self) -> &str {
+                self.0
+            }
+
+            fn register(&self, registrar: &mut Registrar) {
+                let word = self.0;
+                registrar.function(word, "Greets someone.", move |name: &str| {
+                    format!("{word}, {name}!")
+                });
+            }
+        }
+
+        let renderer = Renderer::default()
+            .with_extension(Greeting("hello"))
+            .with_extension(Greeting("bye"));
+        let names: Vec<_> = renderer.extensions().map(|e| e.name()).collect();
+        assert_eq!(names, ["hello", "bye"]);
+
+        let renderer = Arc::new(renderer);
+        let handle = {
+            let renderer = Arc::clone(&renderer);
+            thread::spawn(move || renderer.process("λ#(bye(\"you\"))#").output)
+        };
+        assert_eq!(
+            renderer.process("λ#(hello(\"you\"))#").output,
+            "hello, you!"
+        );
+        assert_eq!(handle.join().unwrap(), "bye, you!");
+    }
+}
@@ -0,0 +1,659 @@
+//! Settings for the Rhai engine that evaluates `{rhai}` chunks and inline
+//! expressions.
+
+use crate::error::RenderError;
+use crate::extension::{register_extension, DraftsmithExtension};
+use crate::processor::CustomFn;
+use rhai::module_resolvers::FileModuleResolver;
+use rhai::packages::{
+    BasicArrayPackage, BasicBlobPackage, BasicMapPackage, BasicMathPackage, BasicTimePackage,
+    BitFieldPackage, CorePackage, LogicPackage, MoreStringPackage, Package,
+};
+use rhai::{Dynamic, Engine, EvalAltResult, Module, ModuleResolver, Position, Scope, Shared};
+use std::cell::Cell;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+/// How often, in operations, a script checks whether it is past its
+/// deadline.
+const DEADLINE_CHECK_INTERVAL: u64 = 256;
+
+/// Limits on what the scripts of a document may do, so that a runaway
+/// chunk such as `loop {}` cannot hang or crash the renderer.
+///
+/// A limit of `None` is turned off. A chunk or expression that hits a limit
+/// stops with an error diagnostic, and the rest of the document is still
+/// rendered.
+///
+/// ```
+/// use draftsmith_render::renderer::Renderer;
+/// use draftsmith_render::script::ScriptLimits;
+/// use std::time::Duration;
+///
+/// let renderer = Renderer::default().with_limits(ScriptLimits {
+///     timeout: Some(Duration::from_millis(100)),
+///     ..ScriptLimits::default()
+/// });
+/// let rendered = renderer.process("```{rhai}\nloop {}\n```");
+/// assert_eq!(rendered.diagnostics.len(), 1);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScriptLimits {
+    /// The most operations a single chunk or expression may run.
+    pub max_operations: Option<u64>,
+    /// How deeply expressions may be nested, also inside functions.
+    pub max_expr_depth: Option<usize>,
+    /// The longest string a script may build, in bytes.
+    pub max_string_size: Option<usize>,
+    /// The most elements an array may hold.
+    pub max_array_size: Option<usize>,
+    /// The most properties an object map may hold.
+    pub max_map_size: Option<usize>,
+    /// How long the scripts of one document may run in total.
+    pub timeout: Option<Duration>,
+}
+
+impl ScriptLimits {
+    /// No limits at all, for trusted documents only.
+    pub fn none() -> Self {
+        Self {
+            max_operations: None,
+            max_expr_depth: None,
+            max_string_size: None,
+            max_array_size: None,
+            max_map_size: None,
+            timeout: None,
+        }
+    }
+
+    /// Sets the limits that belong to the engine itself and installs the
+    /// callback that enforces a [`Budget`].
+    fn apply(&self, engine: &mut Engine) {
+        let depth = self.max_expr_depth.unwrap_or(0);
+        engine.set_max_expr_depths(depth, depth);
+        engine.set_max_string_size(self.max_string_size.unwrap_or(0));
+        engine.set_max_array_size(self.max_array_size.unwrap_or(0));
+        engine.set_max_map_size(self.max_map_size.unwrap_or(0));
+        engine.on_progress(|operations| BUDGET.with(|budget| budget.get().check(operations)));
+    }
+}
+
+impl Default for ScriptLimits {
+    /// Generous limits that ordinary notes never reach.
+    fn default() -> Self {
+        Self {
+            max_operations: Some(1_000_000),
+            max_expr_depth: Some(64),
+            max_string_size: Some(1 << 20),
+            max_array_size: Some(100_000),
+            max_map_size: Some(100_000),
+            timeout: Some(Duration::from_secs(5)),
+        }
+    }
+}
+
+/// A group of Rhai functions that documents may be given.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptPackage {
+    /// The language core: arithmetic, basic string functions, ranges and
+    /// function pointers.
+    Core,
+    /// `sqrt`, `sin`, `round`, number parsing and the like.
+    Math,
+    /// Comparisons between numbers of different types, `min` and `max`.
+    Logic,
+    /// Bit fields of integers.
+    Bits,
+    /// String methods such as `split`, `trim`, `pad` and `sub_string`.
+    Strings,
+    /// Array methods such as `push`, `map`, `filter` and `sort`.
+    Arrays,
+    /// Byte arrays.
+    Blobs,
+    /// Object map methods such as `keys` and `values`.
+    Maps,
+    /// `timestamp()` and elapsed time.
+    Time,
+}
+
+impl ScriptPackage {
+    /// All packages, which together make up Rhai's standard library.
+    pub const ALL: [ScriptPackage; 9] = [
+        ScriptPackage::Core,
+        ScriptPackage::Math,
+        ScriptPackage::Logic,
+        ScriptPackage::Bits,
+        ScriptPackage::Strings,
+        ScriptPackage::Arrays,
+        ScriptPackage::Blobs,
+        ScriptPackage::Maps,
+        ScriptPackage::Time,
+    ];
+
+    /// The name used to select the package, e.g. on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            ScriptPackage::Core => "core",
+            ScriptPackage::Math => "math",
+            ScriptPackage::Logic => "logic",
+            ScriptPackage::Bits => "bits",
+            ScriptPackage::Strings => "strings",
+            ScriptPackage::Arrays => "arrays",
+            ScriptPackage::Blobs => "blobs",
+            ScriptPackage::Maps => "maps",
+            ScriptPackage::Time => "time",
+        }
+    }
+
+    fn register_into(self, engine: &mut Engine) {
+        let module = match self {
+            ScriptPackage::Core => CorePackage::new().as_shared_module(),
+            ScriptPackage::Math => BasicMathPackage::new().as_shared_module(),
+            ScriptPackage::Logic => LogicPackage::new().as_shared_module(),
+            ScriptPackage::Bits => BitFieldPackage::new().as_shared_module(),
+            ScriptPackage::Strings => MoreStringPackage::new().as_shared_module(),
+            ScriptPackage::Arrays => BasicArrayPackage::new().as_shared_module(),
+            ScriptPackage::Blobs => BasicBlobPackage::new().as_shared_module(),
+            ScriptPackage::Maps => BasicMapPackage::new().as_shared_module(),
+            ScriptPackage::Time => BasicTimePackage::new().as_shared_module(),
+        };
+        engine.register_global_module(module);
+    }
+}
+
+impl fmt::Display for ScriptPackage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ScriptPackage {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ScriptPackage::ALL
+            .into_iter()
+            .find(|package| package.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = ScriptPackage::ALL.iter().map(|p| p.name()).collect();
+                format!(
+                    "unknown package `{}`, expected one of: {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// The Rhai packages documents can use.
+///
+/// On the command line, a preset is given by name and a custom list as
+/// package names separated by commas, e.g. `core,math,strings`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum ScriptPackages {
+    /// Only [`ScriptPackage::Core`].
+    Minimal,
+    /// The core and math functions.
+    #[default]
+    Basic,
+    /// Rhai's whole standard library, every [`ScriptPackage`].
+    Standard,
+    /// Exactly the packages listed. Without [`ScriptPackage::Core`] even
+    /// `for` loops over ranges are not available.
+    Custom(Vec<ScriptPackage>),
+}
+
+impl ScriptPackages {
+    /// The presets, in the order they are documented.
+    pub const PRESETS: [ScriptPackages; 3] = [
+        ScriptPackages::Minimal,
+        ScriptPackages::Basic,
+        ScriptPackages::Standard,
+    ];
+
+    /// The packages to register.
+    pub fn packages(&self) -> &[ScriptPackage] {
+        match self {
+            ScriptPackages::Minimal => &[ScriptPackage::Core],
+            ScriptPackages::Basic => &[ScriptPackage::Core, ScriptPackage::Math],
+            ScriptPackages::Standard => &ScriptPackage::ALL,
+            ScriptPackages::Custom(packages) => packages,
+        }
+    }
+
+    /// The name of a preset, `None` for a custom list.
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            ScriptPackages::Minimal => Some("minimal"),
+            ScriptPackages::Basic => Some("basic"),
+            ScriptPackages::Standard => Some("standard"),
+            ScriptPackages::Custom(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for ScriptPackages {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => {
+                let names: Vec<_> = self.packages().iter().map(|p| p.name()).collect();
+                write!(f, "{}", names.join(","))
+            }
+        }
+    }
+}
+
+impl FromStr for ScriptPackages {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(preset) = ScriptPackages::PRESETS.into_iter().find(|preset| {
+            preset
+                .name()
+                .is_some_and(|name| name.eq_ignore_ascii_case(s))
+        }) {
+            return Ok(preset);
+        }
+        s.split(',')
+            .map(|name| name.trim().parse::<ScriptPackage>())
+            .collect::<Result<_, _>>()
+            .map(ScriptPackages::Custom)
+            .map_err(|err| format!("{}, or a preset: minimal, basic, standard", err))
+    }
+}
+
+thread_local! {
+    /// The budget of the script running on this thread.
+    static BUDGET: Cell<Budget> = const { Cell::new(Budget::UNLIMITED) };
+}
+
+/// What is left for the script being evaluated, checked by the engine's
+/// progress callback.
+///
+/// The operation count is part of each evaluation, but the deadline covers
+/// the whole document, so it is kept by the processor rather than the
+/// engine, which may be shared.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Budget {
+    pub max_operations: Option<u64>,
+    pub deadline: Option<Instant>,
+    /// The timeout the deadline was set from, for the error message.
+    pub timeout: Option<Duration>,
+}
+
+impl Budget {
+    const UNLIMITED: Budget = Budget {
+        max_operations: None,
+        deadline: None,
+        timeout: None,
+    };
+
+    /// Runs `f`, evaluating scripts on this thread within the budget.
+    pub(crate) fn run<T>(self, f: impl FnOnce() -> T) -> T {
+        let _previous = RestoreBudget(BUDGET.with(|budget| budget.replace(self)));
+        f()
+    }
+
+    /// Checks the budget after `operations` operations.
+    ///
+    /// # Returns
+    ///
+    /// `None` to go on, or the reason the script is stopped.
+    // `u64::is_multiple_of` is only stable since Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    fn check(&self, operations: u64) -> Option<Dynamic> {
+        if let Some(max) = self.max_operations.filter(|max| operations > *max) {
+            return Some(format!("Script exceeded the limit of {} operations", max).into());
+        }
+        let deadline = self.deadline?;
+        if operations % DEADLINE_CHECK_INTERVAL == 0 && Instant::now() >= deadline {
+            let timeout = self.timeout.unwrap_or_default();
+            return Some(format!("Scripts ran longer than the limit of {:?}", timeout).into());
+        }
+        None
+    }
+}
+
+/// Puts back the budget [`Budget::run`] replaced when dropped, so that it
+/// is restored even if the scripts panic.
+struct RestoreBudget(Budget);
+
+impl Drop for RestoreBudget {
+    fn drop(&mut self) {
+        BUDGET.with(|budget| budget.set(self.0));
+    }
+}
+
+/// Resolves `import "helpers/finance" as fin;` to the file
+/// `helpers/finance.rhai` below a directory.
+///
+/// Imports in a module file are relative to that file. A path that leads
+/// outside the directory, e.g. through `..` or a symbolic link, is refused.
+/// Each module is compiled once and then kept, so the documents rendered by
+/// one engine share it.
+struct ModuleDirectory {
+    root: PathBuf,
+    files: FileModuleResolver,
+}
+
+impl ModuleDirectory {
+    fn new(root: &Path) -> Self {
+        Self {
+            root: root.canonicalize().unwrap_or_else(|_| root.to_path_buf()),
+            files: FileModuleResolver::new(),
+        }
+    }
+}
+
+impl ModuleResolver for ModuleDirectory {
+    fn resolve(
+        &self,
+        engine: &Engine,
+        source: Option<&str>,
+        path: &str,
+        pos: Position,
+    ) -> Result<Shared<rhai::Module>, Box<EvalAltResult>> {
+        // Only module files, whose source is their absolute path in the
+        // directory, import relative to themselves. Preludes, chunks and
+        // expressions import from the root, whatever their source name.
+        let dir = source
+            .map(Path::new)
+            .filter(|source| source.starts_with(&self.root))
+            .and_then(Path::parent)
+            .unwrap_or(&self.root);
+        let mut file = dir.join(path);
+        file.set_extension("rhai");
+        let file = file
+            .canonicalize()
+            .map_err(|_| EvalAltResult::ErrorModuleNotFound(path.to_string(), pos))?;
+        if !file.starts_with(&self.root) {
+            let message = format!("Module `{}` is outside the modules directory", path);
+            return Err(EvalAltResult::ErrorRuntime(message.into(), pos).into());
+        }
+        // The absolute path is also the cache key and the source of the
+        // module, which its own imports are relative to
+        self.files
+            .resolve(engine, None, &file.to_string_lossy(), pos)
+    }
+}
+
+/// A Rhai script whose functions and constants are available to every
+/// chunk and inline expression.
+///
+/// A prelude is compiled once and then shared by every engine it is added
+/// to. Its constants are evaluated when it is compiled, with Rhai's
+/// standard library and the default [`ScriptLimits`], so they cannot use
+/// custom functions.
+///
+/// ```
+/// use draftsmith_render::renderer::Renderer;
+/// use draftsmith_render::script::Prelude;
+///
+/// let prelude = Prelude::compile("lib", "const RATE = 3; fn triple(x) { x * RATE }").unwrap();
+/// let renderer = Renderer::default().with_prelude(prelude);
+/// assert_eq!(renderer.process("λ#(triple(RATE))#").output, "9");
+/// ```
+#[derive(Debug, Clone)]
+pub struct Prelude {
+    name: String,
+    module: Shared<Module>,
+}
+
+impl Prelude {
+    /// Compiles a prelude from source.
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The name errors in the prelude's functions refer to, e.g.
+    ///   its file name.
+    /// * `source` - The Rhai script.
+    ///
+    /// # Returns
+    ///
+    /// The prelude, or the error raised while compiling the script or
+    /// evaluating its constants.
+    pub fn compile(name: impl Into<String>, source: &str) -> Result<Self, RenderError> {
+        let name = name.into();
+        let mut engine = Engine::new_raw();
+        for package in ScriptPackages::Standard.packages() {
+            package.register_into(&mut engine);
+        }
+        let limits = ScriptLimits::default();
+        limits.apply(&mut engine);
+
+        let mut ast = engine
+            .compile(source)
+            .map_err(|err| Box::new(EvalAltResult::from(err)))?;
+        ast.set_source(name.as_str());
+        let mut scope = Scope::new();
+        let budget = Budget {
+            max_operations: limits.max_operations,
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+            timeout: limits.timeout,
+        };
+        budget.run(|| engine.run_ast_with_scope(&mut scope, &ast))?;
+
+        // Only exported variables end up in a module, so the constants are
+        // exported under their own names
+        let mut constants = Scope::new();
+        for (name, constant, value) in scope.iter() {
+            if constant {
+                constants.push_constant(name, value);
+                constants.set_alias(name, name);
+            }
+        }
+        ast.clear_statements();
+        let module = Module::eval_ast_as_new(constants, &ast, &engine)?;
+        Ok(Self {
+            name,
+            module: module.into(),
+        })
+    }
+
+    /// Reads and compiles a prelude file, see [`Prelude::compile`].
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, RenderError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path)?;
+        Self::compile(path.display().to_string(), &source)
+    }
+
+    /// The name of the prelude, e.g. its file name.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// Everything the Rhai engine of a processor or renderer is built from, so
+/// that it can be built again when a setting changes.
+#[derive(Default)]
+pub(crate) struct EngineSettings {
+    pub functions: Vec<CustomFn>,
+    pub limits: ScriptLimits,
+    pub packages: ScriptPackages,
+    /// The directory `import` statements are resolved in, if any.
+    pub modules: Option<PathBuf>,
+    pub preludes: Vec<Prelude>,
+    pub extensions: Vec<Box<dyn DraftsmithExtension>>,
+}
+
+impl EngineSettings {
+    /// Settings with the given custom functions and default limits and
+    /// packages.
+    pub(crate) fn new(functions: Option<Vec<CustomFn>>) -> Self {
+        Self {
+            functions: functions.unwrap_or_default(),
+            ..Self::default()
+        }
+    }
+
+    /// Builds a Rhai engine with the packages, the limits, the preludes, the
+    /// extensions and the custom functions.
+    pub(crate) fn build(&self) -> Engine {
+        let mut engine = Engine::new_raw();
+        for package in self.packages.packages() {
+            package.register_into(&mut engine);
+        }
+        self.limits.apply(&mut engine);
+        if let Some(root) = &self.modules {
+            engine.set_module_resolver(ModuleDirectory::new(root));
+        }
+        for prelude in &self.preludes {
+            engine.register_global_module(prelude.module.clone());
+        }
+        for extension in &self.extensions {
+            register_extension(&mut engine, extension.as_ref());
+        }
+
+        // Register any provided functions with the Rhai engine
+        for register_fn in &self.functions {
+            register_fn(&mut engine);
+        }
+
+        engine
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::Diagnostic;
+    use crate::parser::Span;
+
+    #[test]
+    fn test_budget() {
+        let engine = EngineSettings::default().build();
+        let budget = |max_operations, timeout: Option<Duration>| Budget {
+            max_operations,
+            deadline: timeout.map(|timeout| Instant::now() + timeout),
+            timeout,
+        };
+
+        let err = budget(Some(1000), None)
+            .run(|| engine.eval::<i64>("let x = 0; loop { x += 1; }"))
+            .unwrap_err();
+        let diagnostic = Diagnostic::from_script_error(*err, "```{rhai}", Span::default(), 1, 1, 1);
+        assert_eq!(
+            diagnostic.message,
+            "Script exceeded the limit of 1000 operations"
+        );
+
+        let err = budget(None, Some(Duration::from_millis(50)))
+            .run(|| engine.eval::<i64>("loop {}"))
+            .unwrap_err();
+        assert!(matches!(*err, rhai::EvalAltResult::ErrorTerminated(..)));
+
+        // The budget only applies inside `run`
+        assert_eq!(
+            engine
+                .eval::<i64>("let x = 0; for i in 0..2000 { x += i; } x")
+                .unwrap(),
+            1999000
+        );
+        // or is given up by a panic
+        let panicked = std::panic::catch_unwind(|| {
+            budget(Some(1000), None).run(|| -> i64 { panic!("chunk failed") })
+        });
+        assert!(panicked.is_err());
+        assert!(engine
+            .eval::<i64>("let x = 0; for i in 0..2000 { x += i; } x")
+            .is_ok());
+        let err = engine
+            .eval::<String>("let s = \"ab\"; loop { s += s; }")
+            .unwrap_err();
+        assert!(matches!(*err, rhai::EvalAltResult::ErrorDataTooLarge(..)));
+    }
+
+    #[test]
+    fn test_packages() {
+        assert_eq!("Standard".parse(), Ok(ScriptPackages::Standard));
+        let custom: ScriptPackages = "core, strings,arrays".parse().unwrap();
+        assert_eq!(
+            custom.packages(),
+            [
+                ScriptPackage::Core,
+                ScriptPackage::Strings,
+                ScriptPackage::Arrays
+            ]
+        );
+        assert_eq!(custom.to_string(), "core,strings,arrays");
+        assert!("core,regex"
+            .parse::<ScriptPackages>()
+            .unwrap_err()
+            .starts_with("unknown package `regex`"));
+
+        let engine = |packages| {
+            EngineSettings {
+                packages,
+                ..EngineSettings::default()
+            }
+            .build()
+        };
+        let script = "[3, 1, 2].sort(); \"a,b\".split(\",\").len()";
+        assert!(engine(ScriptPackages::Basic).eval::<i64>(script).is_err());
+        assert_eq!(
+            engine(ScriptPackages::Standard)
+                .eval::<i64>(script)
+                .unwrap(),
+            2
+        );
+        assert_eq!(engine(custom).eval::<i64>(script).unwrap(), 2);
+        assert!(engine(ScriptPackages::Minimal)
+            .eval::<f64>("sqrt(4.0)")
+            .is_err());
+    }
+
+    #[test]
+    fn test_preludes() {
+        let first = Prelude::compile("first", "const GREETING = \"Hello\"; let hidden = 1; fn greet(name) { `${GREETING}, ${name}!` }").unwrap();
+        let second = Prelude::from_file("tests/fixtures/scripts/helpers/format.rhai").unwrap();
+        assert_eq!(second.name(), "tests/fixtures/scripts/helpers/format.rhai");
+        let engine = EngineSettings {
+            preludes: vec![first, second],
+            ..EngineSettings::default()
+        }
+        .build();
+
+        assert_eq!(
+            engine.eval::<String>("greet(\"you\")").unwrap(),
+            "Hello, you!"
+        );
+        assert_eq!(engine.eval::<String>("currency(5)").unwrap(), "$5");
+        assert_eq!(engine.eval::<String>("GREETING").unwrap(), "Hello");
+        assert!(engine.eval::<i64>("hidden").is_err());
+
+        // Imports in a prelude's functions are resolved in the modules
+        // directory, not the working directory
+        let lib = Prelude::compile(
+            "lib",
+            "fn dollars(x) { import \"helpers/format\" as f; f::currency(x) }",
+        )
+        .unwrap();
+        // even if the prelude's name has a directory of its own
+        let nested = Prelude::compile(
+            "lib/x.rhai",
+            "fn price(x) { import \"helpers/format\" as f; f::currency(x) }",
+        )
+        .unwrap();
+        let engine = EngineSettings {
+            modules: Some("tests/fixtures/scripts".into()),
+            preludes: vec![lib, nested],
+            ..EngineSettings::default()
+        }
+        .build();
+        assert_eq!(engine.eval::<String>("dollars(7)").unwrap(), "$7");
+        assert_eq!(engine.eval::<String>("price(8)").unwrap(), "$8");
+
+        assert!(matches!(
+            Prelude::compile("broken", "fn f( {"),
+            Err(RenderError::Script(_))
+        ));
+        assert!(matches!(
+            Prelude::compile("failing", "const X = 1 / 0;"),
+            Err(RenderError::Script(_))
+        ));
+    }
+}
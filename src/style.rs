@@ -0,0 +1,761 @@
+//! The CSS frameworks the generated markup can target.
+
+use crate::admonition::{Admonition, AdmonitionRegistry};
+use crate::parser::Attributes;
+use std::fmt;
+use std::str::FromStr;
+
+/// The title markup of `:::fold` blocks, the same for every backend.
+const FOLD_SUMMARY: &str = "<summary>{title}</summary>";
+
+/// The markup style used for admonitions, tabs, cards, columns and
+/// `{rhai-display}` chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StyleBackend {
+    /// Tailwind CSS with DaisyUI components.
+    #[default]
+    DaisyUi,
+    /// Bootstrap 5.
+    Bootstrap,
+    /// Framework-free semantic HTML (`<aside>`, `<section>`, `<figure>`).
+    Semantic,
+}
+
+/// The heading of one tab in a tab group.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TabHeader {
+    pub label: String,
+    /// Whether the tab is shown when the page loads.
+    pub checked: bool,
+    /// The attributes of the `:::tab` line, added to the tab's panel.
+    pub attributes: Attributes,
+}
+
+impl TabHeader {
+    /// The id of the tab's Bootstrap pane, the one given on its `:::tab`
+    /// line if there is one.
+    fn pane_id(&self, group: &str, index: usize) -> String {
+        self.attributes
+            .id
+            .clone()
+            .unwrap_or_else(|| format!("{}-{}", group, index))
+    }
+}
+
+/// The screen width below which the columns of a `:::columns` block are
+/// stacked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Breakpoint {
+    Sm,
+    Md,
+    #[default]
+    Lg,
+    Xl,
+    Xxl,
+}
+
+impl Breakpoint {
+    /// All breakpoints, from narrowest to widest.
+    pub const ALL: [Breakpoint; 5] = [
+        Breakpoint::Sm,
+        Breakpoint::Md,
+        Breakpoint::Lg,
+        Breakpoint::Xl,
+        Breakpoint::Xxl,
+    ];
+
+    /// The name used in documents, which is also Bootstrap's infix.
+    pub fn name(self) -> &'static str {
+        match self {
+            Breakpoint::Sm => "sm",
+            Breakpoint::Md => "md",
+            Breakpoint::Lg => "lg",
+            Breakpoint::Xl => "xl",
+            Breakpoint::Xxl => "xxl",
+        }
+    }
+
+    /// The Tailwind CSS prefix, which differs from the name for `xxl`.
+    fn tailwind(self) -> &'static str {
+        match self {
+            Breakpoint::Xxl => "2xl",
+            breakpoint => breakpoint.name(),
+        }
+    }
+}
+
+/// The width of one column in a `:::columns` block, written `1/3` or `40%`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColumnWidth {
+    /// A fraction of the row, e.g. `1/3`.
+    Fraction(u32, u32),
+    /// A percentage of the row, e.g. `40%`.
+    Percent(f64),
+}
+
+impl ColumnWidth {
+    /// The width as a percentage of the row.
+    pub fn percent(self) -> f64 {
+        match self {
+            ColumnWidth::Fraction(numerator, denominator) => {
+                f64::from(numerator) * 100.0 / f64::from(denominator)
+            }
+            ColumnWidth::Percent(percent) => percent,
+        }
+    }
+
+    /// The percentage formatted for CSS, rounded to four decimal places.
+    fn css_percent(self) -> String {
+        format!("{}%", (self.percent() * 10_000.0).round() / 10_000.0)
+    }
+
+    /// The Tailwind CSS width class, e.g. `w-1/3`.
+    fn tailwind(self) -> String {
+        match self {
+            ColumnWidth::Fraction(n, d) if n == d => "w-full".to_string(),
+            // Tailwind only has classes for these denominators
+            ColumnWidth::Fraction(n, d) if [2, 3, 4, 5, 6, 12].contains(&d) => {
+                format!("w-{}/{}", n, d)
+            }
+            width => format!("w-[{}]", width.css_percent()),
+        }
+    }
+
+    /// The nearest number of Bootstrap grid columns, out of 12.
+    fn grid_span(self) -> u32 {
+        ((self.percent() * 12.0 / 100.0).round() as u32).clamp(1, 12)
+    }
+}
+
+impl StyleBackend {
+    /// All backends, in the order they are documented.
+    pub const ALL: [StyleBackend; 3] = [
+        StyleBackend::DaisyUi,
+        StyleBackend::Bootstrap,
+        StyleBackend::Semantic,
+    ];
+
+    /// The name used to select the backend, e.g. on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            StyleBackend::DaisyUi => "daisyui",
+            StyleBackend::Bootstrap => "bootstrap",
+            StyleBackend::Semantic => "semantic",
+        }
+    }
+
+    /// The built-in admonitions for this backend.
+    pub fn admonitions(self) -> AdmonitionRegistry {
+        let registry = AdmonitionRegistry::empty();
+        match self {
+            StyleBackend::DaisyUi => registry
+                .with(
+                    "alert",
+                    "<div role=\"alert\" class=\"alert alert-info\">",
+                    "</div>",
+                )
+                .with("info", "<div class=\"admonition note\">", "</div>")
+                .with("success", "<div class=\"alert alert-success\">", "</div>")
+                .with("warning", "<div class=\"admonition important\">", "</div>")
+                .with("error", "<div class=\"admonition warning\">", "</div>")
+                .with("tip", "<div class=\"admonition tip\">", "</div>")
+                .with_admonition(
+                    "fold",
+                    Admonition::new("<details class=\"my-details\">", "</details>")
+                        .with_title(FOLD_SUMMARY),
+                )
+                .with("summary", "<summary class=\"my-summary\">", "</summary>")
+                .with(
+                    "col",
+                    "<div class=\"flex w-full flex-col lg:flex-row\">",
+                    "</div>",
+                )
+                .with(
+                    "card",
+                    "<div class=\"card bg-base-100 shadow-xl\">",
+                    "</div>",
+                ),
+            StyleBackend::Bootstrap => registry
+                .with_title("<h5 class=\"alert-heading\">{title}</h5>")
+                .with(
+                    "alert",
+                    "<div role=\"alert\" class=\"alert alert-info\">",
+                    "</div>",
+                )
+                .with("info", "<div class=\"alert alert-info\">", "</div>")
+                .with("success", "<div class=\"alert alert-success\">", "</div>")
+                .with("warning", "<div class=\"alert alert-warning\">", "</div>")
+                .with(
+                    "error",
+                    "<div role=\"alert\" class=\"alert alert-danger\">",
+                    "</div>",
+                )
+                .with("tip", "<div class=\"alert alert-primary\">", "</div>")
+                .with_admonition(
+                    "fold",
+                    Admonition::new("<details class=\"mb-3\">", "</details>")
+                        .with_title(FOLD_SUMMARY),
+                )
+                .with("summary", "<summary>", "</summary>")
+                .with("col", "<div class=\"row\">", "</div>")
+                .with("card", "<div class=\"card\">", "</div>"),
+            StyleBackend::Semantic => {
+                let mut registry = registry
+                    .with_title("<header class=\"admonition-title\">{title}</header>")
+                    .with(
+                        "alert",
+                        "<aside role=\"alert\" class=\"alert\">",
+                        "</aside>",
+                    )
+                    .with("info", "<aside class=\"note\">", "</aside>")
+                    .with("success", "<aside class=\"success\">", "</aside>")
+                    .with("warning", "<aside class=\"warning\">", "</aside>")
+                    .with(
+                        "error",
+                        "<aside role=\"alert\" class=\"error\">",
+                        "</aside>",
+                    )
+                    .with("tip", "<aside class=\"tip\">", "</aside>")
+                    .with_admonition(
+                        "fold",
+                        Admonition::new("<details>", "</details>").with_title(FOLD_SUMMARY),
+                    )
+                    .with("summary", "<summary>", "</summary>")
+                    .with("col", "<div class=\"columns\">", "</div>")
+                    .with("card", "<article class=\"card\">", "</article>");
+                registry.set_fallback("<section class=\"{name}\">", "</section>");
+                registry
+            }
+        }
+    }
+
+    /// Opens a tab group.
+    ///
+    /// # Arguments
+    ///
+    /// * `group` - A name shared by the tabs of this group.
+    /// * `tabs` - The headings of every tab in the group.
+    pub(crate) fn tabs_open(self, group: &str, tabs: &[TabHeader]) -> String {
+        match self {
+            StyleBackend::DaisyUi => {
+                "<div role=\"tablist\" class=\"tabs tabs-lifted\">\n".to_string()
+            }
+            StyleBackend::Bootstrap => {
+                let mut html = "<ul class=\"nav nav-tabs\" role=\"tablist\">\n".to_string();
+                for (index, tab) in tabs.iter().enumerate() {
+                    let (active, selected) = if tab.checked {
+                        (" active", "true")
+                    } else {
+                        ("", "false")
+                    };
+                    html.push_str(&format!(
+                        "  <li class=\"nav-item\" role=\"presentation\"><button class=\"nav-link{}\" data-bs-toggle=\"tab\" data-bs-target=\"#{}\" type=\"button\" role=\"tab\" aria-selected=\"{}\">{}</button></li>\n",
+                        active,
+                        escape_html(&tab.pane_id(group, index + 1)),
+                        selected,
+                        escape_html(&tab.label)
+                    ));
+                }
+                html.push_str("</ul>\n<div class=\"tab-content\">\n");
+                html
+            }
+            StyleBackend::Semantic => "<section class=\"tabs\">\n".to_string(),
+        }
+    }
+
+    /// Opens the panel of one tab.
+    ///
+    /// # Arguments
+    ///
+    /// * `group` - The name of the tab group.
+    /// * `index` - The 1-based position of the tab in its group.
+    /// * `tab` - The tab's heading, whose attributes are added to the panel.
+    pub(crate) fn tab_open(self, group: &str, index: usize, tab: &TabHeader) -> String {
+        match self {
+            StyleBackend::DaisyUi => {
+                let checked = if tab.checked {
+                    " checked=\"checked\""
+                } else {
+                    ""
+                };
+                let panel = add_attributes(
+                    "<div role=\"tabpanel\" class=\"tab-content bg-base-100 border-base-300 rounded-box p-6\">",
+                    &tab.attributes,
+                );
+                format!(
+                    "  <input type=\"radio\" name=\"{}\" role=\"tab\" class=\"tab\" aria-label=\"{}\"{}/>\n  {}\n",
+                    group,
+                    escape_html(&tab.label),
+                    checked,
+                    panel
+                )
+            }
+            StyleBackend::Bootstrap => {
+                let active = if tab.checked { " show active" } else { "" };
+                // The pane already has an id, which the tab list refers to
+                let attributes = Attributes {
+                    id: None,
+                    ..tab.attributes.clone()
+                };
+                let panel = format!(
+                    "  <div class=\"tab-pane fade{}\" id=\"{}\" role=\"tabpanel\">\n",
+                    active,
+                    escape_html(&tab.pane_id(group, index))
+                );
+                add_attributes(&panel, &attributes)
+            }
+            StyleBackend::Semantic => {
+                let open = if tab.checked { " open" } else { "" };
+                let panel = format!(
+                    "  <details name=\"{}\"{}><summary>{}</summary>\n",
+                    group,
+                    open,
+                    escape_html(&tab.label)
+                );
+                add_attributes(&panel, &tab.attributes)
+            }
+        }
+    }
+
+    /// Closes the panel of one tab.
+    pub(crate) fn tab_close(self) -> &'static str {
+        match self {
+            StyleBackend::DaisyUi | StyleBackend::Bootstrap => "  </div>\n",
+            StyleBackend::Semantic => "  </details>\n",
+        }
+    }
+
+    /// Closes a tab group.
+    pub(crate) fn tabs_close(self) -> &'static str {
+        match self {
+            StyleBackend::DaisyUi | StyleBackend::Bootstrap => "</div>\n",
+            StyleBackend::Semantic => "</section>\n",
+        }
+    }
+
+    /// Opens a `:::columns` block.
+    ///
+    /// # Arguments
+    ///
+    /// * `breakpoint` - The width below which the columns are stacked.
+    pub(crate) fn columns_open(self, breakpoint: Breakpoint) -> String {
+        match self {
+            StyleBackend::DaisyUi => format!(
+                "<div class=\"flex w-full flex-col gap-4 {}:flex-row\">\n",
+                breakpoint.tailwind()
+            ),
+            StyleBackend::Bootstrap => "<div class=\"row\">\n".to_string(),
+            StyleBackend::Semantic => format!(
+                "<div class=\"columns\" data-breakpoint=\"{}\">\n",
+                breakpoint.name()
+            ),
+        }
+    }
+
+    /// Opens one column of a `:::columns` block.
+    ///
+    /// # Arguments
+    ///
+    /// * `breakpoint` - The width below which the columns are stacked.
+    /// * `width` - The width of the column, `None` to share the space left.
+    pub(crate) fn column_open(self, breakpoint: Breakpoint, width: Option<ColumnWidth>) -> String {
+        match (self, width) {
+            (StyleBackend::DaisyUi, Some(width)) => format!(
+                "  <div class=\"{}:{}\">\n",
+                breakpoint.tailwind(),
+                width.tailwind()
+            ),
+            (StyleBackend::DaisyUi, None) => "  <div class=\"flex-1\">\n".to_string(),
+            (StyleBackend::Bootstrap, Some(width)) => format!(
+                "  <div class=\"col-{}-{}\">\n",
+                breakpoint.name(),
+                width.grid_span()
+            ),
+            (StyleBackend::Bootstrap, None) => {
+                format!("  <div class=\"col-{}\">\n", breakpoint.name())
+            }
+            (StyleBackend::Semantic, Some(width)) => format!(
+                "  <div class=\"column\" style=\"flex-basis: {}\">\n",
+                width.css_percent()
+            ),
+            (StyleBackend::Semantic, None) => "  <div class=\"column\">\n".to_string(),
+        }
+    }
+
+    /// Closes one column.
+    pub(crate) fn column_close(self) -> &'static str {
+        "  </div>\n"
+    }
+
+    /// Closes a `:::columns` block.
+    pub(crate) fn columns_close(self) -> &'static str {
+        "</div>\n"
+    }
+
+    /// The inside of a `:::card` block.
+    ///
+    /// # Arguments
+    ///
+    /// * `image` - The source and alt text of the card's image.
+    /// * `title` - The card's title, empty for none.
+    /// * `body` - The rendered body.
+    /// * `actions` - The rendered `:::actions` section.
+    pub(crate) fn card(
+        self,
+        image: Option<(&str, &str)>,
+        title: &str,
+        body: &str,
+        actions: Option<&str>,
+    ) -> String {
+        let image = image.map(|(src, alt)| {
+            let class = match self {
+                StyleBackend::Bootstrap => " class=\"card-img-top\"",
+                StyleBackend::DaisyUi | StyleBackend::Semantic => "",
+            };
+            format!(
+                "<img src=\"{}\" alt=\"{}\"{} />",
+                escape_html(src),
+                escape_html(alt),
+                class
+            )
+        });
+        let title = escape_html(title);
+        let mut html = String::new();
+        match self {
+            StyleBackend::DaisyUi => {
+                if let Some(image) = image {
+                    html.push_str(&format!("<figure>{}</figure>\n", image));
+                }
+                html.push_str("<div class=\"card-body\">\n");
+                if !title.is_empty() {
+                    html.push_str(&format!("<h2 class=\"card-title\">{}</h2>\n", title));
+                }
+                push_markdown(&mut html, body);
+                if let Some(actions) = actions {
+                    html.push_str("<div class=\"card-actions justify-end\">\n");
+                    push_markdown(&mut html, actions);
+                    html.push_str("</div>\n");
+                }
+                html.push_str("</div>\n");
+            }
+            StyleBackend::Bootstrap => {
+                if let Some(image) = image {
+                    html.push_str(&format!("{}\n", image));
+                }
+                html.push_str("<div class=\"card-body\">\n");
+                if !title.is_empty() {
+                    html.push_str(&format!("<h5 class=\"card-title\">{}</h5>\n", title));
+                }
+                push_markdown(&mut html, body);
+                html.push_str("</div>\n");
+                if let Some(actions) = actions {
+                    html.push_str("<div class=\"card-footer\">\n");
+                    push_markdown(&mut html, actions);
+                    html.push_str("</div>\n");
+                }
+            }
+            StyleBackend::Semantic => {
+                if let Some(image) = image {
+                    html.push_str(&format!("{}\n", image));
+                }
+                if !title.is_empty() {
+                    html.push_str(&format!("<header><h3>{}</h3></header>\n", title));
+                }
+                html.push_str("<div class=\"card-body\">\n");
+                push_markdown(&mut html, body);
+                html.push_str("</div>\n");
+                if let Some(actions) = actions {
+                    html.push_str("<footer>\n");
+                    push_markdown(&mut html, actions);
+                    html.push_str("</footer>\n");
+                }
+            }
+        }
+        html
+    }
+
+    /// Opens a `:::cards` grid.
+    ///
+    /// # Arguments
+    ///
+    /// * `columns` - The number of cards per row on wide screens.
+    pub(crate) fn cards_open(self, columns: usize) -> String {
+        match self {
+            StyleBackend::DaisyUi => format!(
+                "<div class=\"grid grid-cols-1 gap-4 md:grid-cols-2 lg:grid-cols-{}\">\n",
+                columns
+            ),
+            StyleBackend::Bootstrap => format!(
+                "<div class=\"row row-cols-1 row-cols-md-2 row-cols-lg-{} g-4\">\n",
+                columns
+            ),
+            StyleBackend::Semantic => {
+                format!("<div class=\"cards\" data-columns=\"{}\">\n", columns)
+            }
+        }
+    }
+
+    /// Wraps each card of a `:::cards` grid, if the backend needs it.
+    pub(crate) fn cards_item(self) -> (&'static str, &'static str) {
+        match self {
+            StyleBackend::Bootstrap => ("<div class=\"col\">\n", "</div>\n"),
+            StyleBackend::DaisyUi | StyleBackend::Semantic => ("", ""),
+        }
+    }
+
+    /// Closes a `:::cards` grid.
+    pub(crate) fn cards_close(self) -> &'static str {
+        "</div>\n"
+    }
+
+    /// Wraps the source and result of a `{rhai-display}` chunk.
+    ///
+    /// # Arguments
+    ///
+    /// * `code` - The Rhai source.
+    /// * `result` - The formatted result, or `None` if the chunk failed.
+    pub(crate) fn rhai_display(self, code: &str, result: Option<&str>) -> String {
+        let (open, close, out_open, out_close) = match self {
+            StyleBackend::DaisyUi => (
+                "<div class=\"rhai-display\">",
+                "</div>",
+                "<div class=\"rhai-out\">",
+                "</div>",
+            ),
+            StyleBackend::Bootstrap => (
+                "<div class=\"rhai-display card mb-3\">",
+                "</div>",
+                "<div class=\"rhai-out card-footer\">",
+                "</div>",
+            ),
+            StyleBackend::Semantic => (
+                "<figure class=\"rhai-display\">",
+                "</figure>",
+                "<figcaption class=\"rhai-out\">",
+                "</figcaption>",
+            ),
+        };
+
+        let mut html = format!("{}\n\n```rust\n{}\n```\n", open, code);
+        if let Some(result) = result {
+            html.push_str(&format!(
+                "{}\n\n```\n{}\n```\n{}\n",
+                out_open, result, out_close
+            ));
+        }
+        html.push_str(close);
+        html.push('\n');
+        html
+    }
+}
+
+/// Appends markdown between blank lines, so that it is rendered rather
+/// than read as part of the HTML block around it.
+fn push_markdown(html: &mut String, markdown: &str) {
+    let markdown = markdown.trim_matches('\n');
+    if !markdown.is_empty() {
+        html.push('\n');
+        html.push_str(markdown);
+        html.push_str("\n\n");
+    }
+}
+
+/// Escapes text for use in HTML content and attribute values.
+pub(crate) fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Adds attributes to the first tag of an HTML fragment.
+///
+/// Classes are appended to the tag's `class` attribute if it has one.
+///
+/// # Arguments
+///
+/// * `html` - Markup starting with an opening tag, e.g. `<div class="tip">`.
+/// * `attributes` - The attributes to add.
+///
+/// # Returns
+///
+/// The markup with the attributes added, or unchanged if it does not contain
+/// a tag.
+pub(crate) fn add_attributes(html: &str, attributes: &Attributes) -> String {
+    if attributes.is_empty() {
+        return html.to_string();
+    }
+    let Some(start) = html.find('<') else {
+        return html.to_string();
+    };
+    let Some(len) = html[start..].find('>') else {
+        return html.to_string();
+    };
+    let end = start + len;
+    let (mut tag, rest) = (html[..end].to_string(), &html[end..]);
+    let self_closing = tag.ends_with('/');
+    if self_closing {
+        tag.pop();
+    }
+
+    let mut extra = String::new();
+    if !attributes.classes.is_empty() {
+        let classes = escape_html(&attributes.classes.join(" "));
+        match tag.find(" class=\"") {
+            Some(class_start) => {
+                let value_start = class_start + " class=\"".len();
+                let value_end = value_start + tag[value_start..].find('"').unwrap_or(0);
+                tag.insert_str(value_end, &format!(" {}", classes));
+            }
+            None => extra.push_str(&format!(" class=\"{}\"", classes)),
+        }
+    }
+    if let Some(id) = &attributes.id {
+        extra.push_str(&format!(" id=\"{}\"", escape_html(id)));
+    }
+    for (key, value) in &attributes.pairs {
+        if value.is_empty() {
+            extra.push_str(&format!(" {}", key));
+        } else {
+            extra.push_str(&format!(" {}=\"{}\"", key, escape_html(value)));
+        }
+    }
+
+    let tag = tag.trim_end();
+    let slash = if self_closing { "/" } else { "" };
+    format!("{}{}{}{}", tag, extra, slash, rest)
+}
+
+impl fmt::Display for StyleBackend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for StyleBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        StyleBackend::ALL
+            .into_iter()
+            .find(|backend| backend.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = StyleBackend::ALL.iter().map(|b| b.name()).collect();
+                format!(
+                    "unknown style `{}`, expected one of: {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("2xl") {
+            return Ok(Breakpoint::Xxl);
+        }
+        Breakpoint::ALL
+            .into_iter()
+            .find(|breakpoint| breakpoint.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = Breakpoint::ALL.iter().map(|b| b.name()).collect();
+                format!(
+                    "unknown breakpoint `{}`, expected one of: {}",
+                    s,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+impl FromStr for ColumnWidth {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let error = || format!("`{}` is not a column width like `1/3` or `40%`", s);
+        if let Some(percent) = s.strip_suffix('%') {
+            return match percent.trim().parse::<f64>() {
+                Ok(percent) if percent > 0.0 && percent <= 100.0 => {
+                    Ok(ColumnWidth::Percent(percent))
+                }
+                _ => Err(error()),
+            };
+        }
+        let (numerator, denominator) = s.split_once('/').ok_or_else(error)?;
+        match (
+            numerator.trim().parse::<u32>(),
+            denominator.trim().parse::<u32>(),
+        ) {
+            (Ok(n), Ok(d)) if n > 0 && n <= d => Ok(ColumnWidth::Fraction(n, d)),
+            _ => Err(error()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_names() {
+        for backend in StyleBackend::ALL {
+            assert_eq!(backend.name().parse::<StyleBackend>(), Ok(backend));
+        }
+        assert_eq!("Bootstrap".parse(), Ok(StyleBackend::Bootstrap));
+        assert!("tailwind".parse::<StyleBackend>().is_err());
+    }
+
+    #[test]
+    fn test_add_attributes() {
+        let attributes = Attributes::parse("#note .wide data-x=\"a<b\" hidden").unwrap();
+
+        assert_eq!(
+            add_attributes("<div class=\"admonition tip\"><p>x</p>", &attributes),
+            "<div class=\"admonition tip wide\" id=\"note\" data-x=\"a&lt;b\" hidden><p>x</p>"
+        );
+        assert_eq!(
+            add_attributes("<aside>", &attributes),
+            "<aside class=\"wide\" id=\"note\" data-x=\"a&lt;b\" hidden>"
+        );
+        assert_eq!(
+            add_attributes("<hr />", &Attributes::parse("#x").unwrap()),
+            "<hr id=\"x\"/>"
+        );
+        assert_eq!(add_attributes("plain", &attributes), "plain");
+    }
+
+    #[test]
+    fn test_column_widths() {
+        let third: ColumnWidth = "1/3".parse().unwrap();
+        let forty: ColumnWidth = "40%".parse().unwrap();
+        let sevenths: ColumnWidth = "2/7".parse().unwrap();
+
+        assert_eq!(third.tailwind(), "w-1/3");
+        assert_eq!(forty.tailwind(), "w-[40%]");
+        assert_eq!(sevenths.tailwind(), "w-[28.5714%]");
+        assert_eq!((third.grid_span(), forty.grid_span()), (4, 5));
+        assert!("4/3".parse::<ColumnWidth>().is_err());
+        assert!("0%".parse::<ColumnWidth>().is_err());
+        assert!("wide".parse::<ColumnWidth>().is_err());
+        assert_eq!("2XL".parse(), Ok(Breakpoint::Xxl));
+        assert!("huge".parse::<Breakpoint>().is_err());
+    }
+
+    #[test]
+    fn test_rhai_display_without_result() {
+        assert_eq!(
+            StyleBackend::Semantic.rhai_display("1 +", None),
+            "<figure class=\"rhai-display\">\n\n```rust\n1 +\n```\n</figure>\n"
+        );
+    }
+}